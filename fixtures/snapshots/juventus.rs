@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Juventus {
+    pub name: String,
+    pub position: String,
+    #[serde(rename = "DOB")]
+    pub dob: String,
+    pub nationality: String,
+    #[serde(rename = "Kit Number")]
+    pub kit_number: i64,
+}
@@ -0,0 +1,7 @@
+export interface Juventus {
+  Name: string;
+  Position: string;
+  DOB: string;
+  Nationality: string;
+  "Kit Number": number;
+}
@@ -0,0 +1,25 @@
+use super::csv::{parse_format, OutputFormat};
+use super::verify_file;
+use crate::process_convert;
+use crate::CmdExector;
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+pub struct ConvertOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// Input format; detected from the file extension or the content when omitted
+    #[arg(long, value_parser = parse_format)]
+    pub from: Option<OutputFormat>,
+    /// Output format
+    #[arg(short, long, alias = "to", value_parser = parse_format)]
+    pub format: OutputFormat,
+}
+
+impl CmdExector for ConvertOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_convert(&self.input, &self.output, self.from, self.format)
+    }
+}
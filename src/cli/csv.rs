@@ -1,54 +1,447 @@
 use super::verify_file;
-use crate::process_csv;
+use crate::process::table::TableStyle;
 use crate::CmdExector;
-use clap::Parser;
+use crate::{
+    process_csv, process_csv_cat, process_csv_codegen, process_csv_join, process_csv_show,
+    process_csv_split, process_csv_stats, process_csv_validate, process_to_csv,
+};
+use clap::{ArgAction, ArgGroup, Args, Parser};
+use encoding_rs::{Encoding, UTF_8};
+use enum_dispatch::enum_dispatch;
 use std::fmt;
+use std::io::IsTerminal;
 use std::str::FromStr;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum OutputFormat {
     Json,
+    Ndjson,
     Yaml,
     Toml,
+    Markdown,
+    Html,
+    MsgPack,
+    Cbor,
 }
 
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct CsvOpts {
-    #[arg(short, long, value_parser = verify_file)]
+    #[command(subcommand)]
+    pub cmd: Option<CsvSubCommand>,
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
+    /// Output file, `-` for stdout; defaults to stdout when reading stdin, else `output.<format>`
     #[arg(short, long)] // can use into()
     pub output: Option<String>,
     #[arg(short, long, default_value = "json", value_parser = parse_format)] // can use into()
     pub format: OutputFormat,
-    #[arg(short, long, default_value_t = ',')]
-    pub delimiter: char,
-    #[arg(short = 'H', long, default_value_t = true)]
+    /// Read an array of objects in this format and convert it back into csv
+    #[arg(long, value_parser = parse_format, conflicts_with = "format")]
+    pub from: Option<OutputFormat>,
+    /// Turn nested objects and arrays into `address.city` and `tags[0]` columns
+    #[arg(long, requires = "from")]
+    pub flatten: bool,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub select: CsvSelectOpts,
+    #[command(flatten)]
+    pub convert: CsvConvertOpts,
+    #[command(flatten)]
+    pub show: CsvShowOpts,
+}
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExector)]
+pub enum CsvSubCommand {
+    #[command(name = "stats", about = "Profile each column of a csv file")]
+    Stats(CsvStatsOpts),
+    #[command(name = "join", about = "Join two csv files on key columns")]
+    Join(CsvJoinOpts),
+    #[command(
+        name = "validate",
+        about = "Check every row of a csv file against a schema"
+    )]
+    Validate(CsvValidateOpts),
+    #[command(
+        name = "codegen",
+        about = "Generate a typed record definition from a csv file"
+    )]
+    Codegen(CsvCodegenOpts),
+    #[command(
+        name = "split",
+        about = "Split a csv file into chunks that each keep the header"
+    )]
+    Split(CsvSplitOpts),
+    #[command(
+        name = "cat",
+        about = "Concatenate csv files that have the same columns"
+    )]
+    Cat(CsvCatOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvStatsOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// Write the profile in this format instead of a table
+    #[arg(short, long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+    /// Number of most frequent values reported per column
+    #[arg(long, default_value_t = 5)]
+    pub top: usize,
+    /// Estimate distinct counts with HyperLogLog and bound the memory used for top values
+    #[arg(long)]
+    pub approx: bool,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub select: CsvSelectOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvJoinOpts {
+    #[arg(value_parser = verify_file)]
+    pub left: String,
+    #[arg(value_parser = verify_file)]
+    pub right: String,
+    /// Key columns, e.g. `id`, or `left_id=right_id` when the names differ
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub on: Vec<String>,
+    /// Join mode: inner, left or full
+    #[arg(long, default_value = "inner", value_parser = parse_join_kind)]
+    pub how: JoinKind,
+    /// Appended to right columns whose name is already taken, repeatedly if need be
+    #[arg(long, default_value = "_right")]
+    pub suffix: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(short, long, default_value = "json", value_parser = parse_format)]
+    pub format: OutputFormat,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub convert: CsvConvertOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvValidateOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// JSON Schema, or a toml column spec when the file ends in `.toml`
+    #[arg(long, value_parser = verify_file)]
+    pub schema: String,
+    /// Where violations are reported, one per line
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvCodegenOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// Target language: rust, typescript or json-schema
+    #[arg(long, default_value = "rust", value_parser = parse_codegen_lang)]
+    pub lang: CodegenLang,
+    /// Type name, defaults to the input file name
+    #[arg(long)]
+    pub name: Option<String>,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+    #[command(flatten)]
+    pub select: CsvSelectOpts,
+}
+
+#[derive(Debug, Parser)]
+#[command(group(ArgGroup::new("mode").required(true).args(["rows", "by"])))]
+pub struct CsvSplitOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Directory the chunks are written to, as `<input name>_<chunk>.<ext>`
+    #[arg(short, long, default_value = ".")]
+    pub out_dir: String,
+    /// Start a new chunk every this many rows
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub rows: Option<u64>,
+    /// Write one chunk per distinct value of this column
+    #[arg(long)]
+    pub by: Option<String>,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvCatOpts {
+    /// Files to concatenate; columns are reordered to match the first one
+    #[arg(required = true, value_parser = verify_file)]
+    pub inputs: Vec<String>,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodegenLang {
+    /// A serde struct
+    Rust,
+    TypeScript,
+    JsonSchema,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JoinKind {
+    /// Only rows with a match on both sides
+    Inner,
+    /// Every left row, with empty right columns when unmatched
+    Left,
+    /// Every row from both sides
+    Full,
+}
+
+/// Options that control how the csv input is parsed.
+#[derive(Clone, Debug, Args)]
+pub struct CsvReaderOpts {
+    /// Field delimiter, a single ascii character; `\t` or `tab` for tsv
+    #[arg(short, long, default_value = ",", value_parser = parse_delimiter)]
+    pub delimiter: u8,
+    /// Whether the first row is a header; use `--header false` for headerless input or output
+    #[arg(short = 'H', long, default_value_t = true, action = ArgAction::Set)]
     // _t doesn't convert data type, must  compare.
     pub header: bool,
+    /// Input encoding: auto, utf-8, utf-16le, gbk, latin1 or another WHATWG label
+    #[arg(long, default_value = "utf-8", value_parser = parse_encoding)]
+    pub encoding: InputEncoding,
+}
+
+/// Encoding of the csv input, transcoded to utf-8 before parsing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputEncoding {
+    /// Sniff a BOM, then check for utf-8, then guess
+    Auto,
+    Fixed(&'static Encoding),
+}
+
+/// Options that pick rows and pick, reorder and rename columns.
+#[derive(Clone, Debug, Default, Args)]
+pub struct CsvSelectOpts {
+    /// Keep only these columns, in this order, e.g. `Name,Position`
+    #[arg(long, value_delimiter = ',')]
+    pub select: Vec<String>,
+    /// Drop these columns
+    #[arg(long, value_delimiter = ',')]
+    pub exclude: Vec<String>,
+    /// Rename columns, e.g. `"Kit Number=kit"`
+    #[arg(long, value_delimiter = ',', value_parser = parse_rename)]
+    pub rename: Vec<(String, String)>,
+    /// Keep rows matching an expression, e.g. `Position == "Defender" && `Kit Number` > 10`
+    #[arg(long = "where")]
+    pub filter: Option<String>,
+    /// Sort by these columns, natural order, e.g. `"Kit Number:desc,Name"`
+    #[arg(long, value_delimiter = ',')]
+    pub sort_by: Vec<String>,
+    /// Keep only the first row for each distinct value of these columns
+    #[arg(long, value_delimiter = ',')]
+    pub unique_by: Vec<String>,
+}
+
+/// Options that control how csv records are turned into values.
+#[derive(Clone, Debug, Args)]
+pub struct CsvConvertOpts {
+    /// Infer integers, floats, booleans and nulls per column instead of emitting strings
+    #[arg(long)]
+    pub infer_types: bool,
+    /// Key of the array of tables that holds the records in toml output
+    #[arg(long, default_value = "records")]
+    pub toml_key: String,
+    /// Build nested objects and arrays from `address.city` and `tags[0]` headers
+    #[arg(long)]
+    pub unflatten: bool,
+}
+
+impl Default for CsvConvertOpts {
+    fn default() -> Self {
+        Self {
+            infer_types: false,
+            toml_key: "records".into(),
+            unflatten: false,
+        }
+    }
+}
+
+/// Options for showing csv as a table instead of converting it.
+#[derive(Clone, Debug, Args)]
+pub struct CsvShowOpts {
+    /// Show records as a table on stdout instead of converting them
+    #[arg(short, long, conflicts_with_all = ["output", "from"])]
+    pub show: bool,
+    /// Show at most this many records
+    #[arg(long, requires = "show")]
+    pub limit: Option<usize>,
+    /// Skip this many records before showing
+    #[arg(long, default_value_t = 0, requires = "show")]
+    pub offset: usize,
+    /// Truncate cells wider than this many columns
+    #[arg(long, default_value_t = 32)]
+    pub max_width: usize,
+}
+
+impl Default for CsvReaderOpts {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            header: true,
+            encoding: InputEncoding::Fixed(UTF_8),
+        }
+    }
+}
+
+impl CsvOpts {
+    fn output_or(&self, ext: &str) -> String {
+        match &self.output {
+            Some(s) => s.clone(),
+            None if self.input == "-" => "-".into(),
+            None => format!("output.{}", ext),
+        }
+    }
 }
 
 impl CmdExector for CsvOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let output = if let Some(s) = self.output {
-            s.clone()
+        if let Some(cmd) = self.cmd {
+            return cmd.execute().await;
+        }
+        if self.show.show {
+            print!(
+                "{}",
+                process_csv_show(
+                    &self.input,
+                    &self.reader,
+                    &self.select,
+                    &self.show,
+                    table_style()
+                )?
+            );
+            return Ok(());
+        }
+        if let Some(from) = self.from {
+            let output = self.output_or("csv");
+            return process_to_csv(&self.input, &output, from, &self.reader, self.flatten);
+        }
+        let output = self.output_or(self.format.into());
+        process_csv(
+            &self.input,
+            &output,
+            self.format,
+            &self.reader,
+            &self.select,
+            &self.convert,
+        )?;
+        Ok(())
+    }
+}
+
+impl CmdExector for CsvStatsOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let style = if self.output == "-" {
+            table_style()
         } else {
-            format!("output.{}", self.format)
+            TableStyle::Plain
         };
-        process_csv(&self.input, &output, self.format)?;
-        Ok(())
+        process_csv_stats(&self, style)
+    }
+}
+
+impl CmdExector for CsvJoinOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_join(&self)
+    }
+}
+
+impl CmdExector for CsvValidateOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_validate(&self.input, &self.output, &self.schema, &self.reader)
+    }
+}
+
+impl CmdExector for CsvCodegenOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_codegen(&self)
+    }
+}
+
+impl CmdExector for CsvSplitOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_split(&self)
+    }
+}
+
+impl CmdExector for CsvCatOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_cat(&self)
     }
 }
 
-fn parse_format(format: &str) -> Result<OutputFormat, anyhow::Error> {
+/// Box drawing only makes sense on a terminal, pipes get plain aligned text.
+fn table_style() -> TableStyle {
+    if std::io::stdout().is_terminal() {
+        TableStyle::Boxed
+    } else {
+        TableStyle::Plain
+    }
+}
+
+pub(crate) fn parse_format(format: &str) -> Result<OutputFormat, anyhow::Error> {
     format.parse()
 }
 
+fn parse_codegen_lang(lang: &str) -> Result<CodegenLang, anyhow::Error> {
+    lang.parse()
+}
+
+fn parse_join_kind(kind: &str) -> Result<JoinKind, anyhow::Error> {
+    kind.parse()
+}
+
+fn parse_encoding(encoding: &str) -> Result<InputEncoding, anyhow::Error> {
+    encoding.parse()
+}
+
+fn parse_rename(rename: &str) -> Result<(String, String), anyhow::Error> {
+    match rename.split_once('=') {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() => Ok((from.into(), to.into())),
+        _ => Err(anyhow::anyhow!("Rename must look like `old=new`")),
+    }
+}
+
+fn parse_delimiter(delimiter: &str) -> Result<u8, anyhow::Error> {
+    match delimiter {
+        "\\t" | "\t" | "tab" => Ok(b'\t'),
+        s if s.len() == 1 && s.is_ascii() => Ok(s.as_bytes()[0]),
+        _ => Err(anyhow::anyhow!(
+            "Delimiter must be a single ascii character"
+        )),
+    }
+}
+
 impl From<OutputFormat> for &'static str {
     fn from(value: OutputFormat) -> Self {
         match value {
             OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
             OutputFormat::Yaml => "yaml",
             OutputFormat::Toml => "toml",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Html => "html",
+            OutputFormat::MsgPack => "msgpack",
+            OutputFormat::Cbor => "cbor",
         }
     }
 }
@@ -60,15 +453,83 @@ impl FromStr for OutputFormat {
         let s = s.as_str();
         match s {
             "json" => Ok(OutputFormat::Json),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
             "yaml" => Ok(OutputFormat::Yaml),
             "toml" => Ok(OutputFormat::Toml),
+            "markdown" | "md" => Ok(OutputFormat::Markdown),
+            "html" => Ok(OutputFormat::Html),
+            "msgpack" | "messagepack" => Ok(OutputFormat::MsgPack),
+            "cbor" => Ok(OutputFormat::Cbor),
             _ => Err(anyhow::anyhow!("Invalid format")),
         }
     }
 }
 
+impl FromStr for InputEncoding {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(InputEncoding::Auto);
+        }
+        Encoding::for_label(s.as_bytes())
+            .map(InputEncoding::Fixed)
+            .ok_or_else(|| anyhow::anyhow!("Unknown encoding `{}`", s))
+    }
+}
+
+impl FromStr for CodegenLang {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rust" | "rs" => Ok(CodegenLang::Rust),
+            "typescript" | "ts" => Ok(CodegenLang::TypeScript),
+            "json-schema" | "jsonschema" => Ok(CodegenLang::JsonSchema),
+            _ => Err(anyhow::anyhow!(
+                "Language must be one of rust, typescript or json-schema"
+            )),
+        }
+    }
+}
+
+impl FromStr for JoinKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "inner" => Ok(JoinKind::Inner),
+            "left" => Ok(JoinKind::Left),
+            "full" | "outer" | "full-outer" => Ok(JoinKind::Full),
+            _ => Err(anyhow::anyhow!("Join must be one of inner, left or full")),
+        }
+    }
+}
+
 impl fmt::Display for OutputFormat {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", Into::<&str>::into(*self))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_delimiter() {
+        assert_eq!(parse_delimiter(",").unwrap(), b',');
+        assert_eq!(parse_delimiter(";").unwrap(), b';');
+        assert_eq!(parse_delimiter("\\t").unwrap(), b'\t');
+        assert_eq!(parse_delimiter("tab").unwrap(), b'\t');
+        assert!(parse_delimiter(";;").is_err());
+        assert!(parse_delimiter("é").is_err());
+    }
+
+    #[test]
+    fn test_parse_rename() {
+        assert_eq!(
+            parse_rename("Kit Number=kit").unwrap(),
+            ("Kit Number".into(), "kit".into())
+        );
+        assert!(parse_rename("kit").is_err());
+        assert!(parse_rename("=kit").is_err());
+    }
+}
@@ -1,30 +1,197 @@
-use crate::process_genpass;
+use super::csv::CsvReaderOpts;
+use super::verify_file;
 use crate::CmdExector;
-use clap::Parser;
+use crate::{process_genpass, process_genpass_check, process_genphrase, StrengthReport};
+use clap::{ArgAction, Args, Parser};
+use enum_dispatch::enum_dispatch;
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct GenPassOpts {
-    #[arg(short, long, default_value_t = 16)]
+    #[command(subcommand)]
+    pub cmd: Option<GenPassSubCommand>,
+    #[arg(short, long, default_value_t = 16, value_parser = clap::value_parser!(u8).range(1..))]
     pub length: u8,
-    #[arg(long, default_value_t = true)]
+    #[command(flatten)]
+    pub charset: CharsetOpts,
+    /// Regenerate until zxcvbn scores the result at least this, from 0 to 4
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=4))]
+    pub min_score: u8,
+    /// How the strength is reported: text on stderr, or json with the password on stdout
+    #[arg(long, default_value = "text", value_parser = parse_report_format)]
+    pub report: ReportFormat,
+    /// Generate a passphrase of this many words instead of a password
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..))]
+    pub words: Option<u8>,
+    /// Separator between passphrase words
+    #[arg(long, default_value = "-", requires = "words")]
+    pub separator: String,
+    /// Capitalize each passphrase word
+    #[arg(long, requires = "words")]
+    pub capitalize: bool,
+    /// Append a digit to one of the passphrase words
+    #[arg(long, requires = "words")]
+    pub digit: bool,
+    /// Word list to draw from instead of the bundled one, e.g. the EFF long list
+    #[arg(long, value_parser = verify_file, requires = "words")]
+    pub wordlist: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExector)]
+pub enum GenPassSubCommand {
+    #[command(name = "check", about = "Rate existing passwords with zxcvbn")]
+    Check(GenPassCheckOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct GenPassCheckOpts {
+    /// Passwords, one per line, or a csv file with `--column`
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// Read the input as csv and check the passwords in this column
+    #[arg(long)]
+    pub column: Option<String>,
+    /// Words passwords shouldn't be built from, e.g. the company name
+    #[arg(long = "user-input", value_delimiter = ',')]
+    pub user_inputs: Vec<String>,
+    /// File of such words, one per line
+    #[arg(long, value_parser = verify_file)]
+    pub user_dict: Option<String>,
+    /// Print each password next to its rating
+    #[arg(long)]
+    pub show: bool,
+    /// Text, one line per password, or a json array
+    #[arg(long, default_value = "text", value_parser = parse_report_format)]
+    pub report: ReportFormat,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+/// Options that pick the characters a password is drawn from.
+#[derive(Clone, Debug, Args)]
+pub struct CharsetOpts {
+    /// Leave out uppercase letters
+    #[arg(long = "no-uppercase", action = ArgAction::SetFalse)]
     pub uppercase: bool,
-    #[arg(long, default_value_t = true)]
+    /// Leave out lowercase letters
+    #[arg(long = "no-lowercase", action = ArgAction::SetFalse)]
     pub lowercase: bool,
-    #[arg(long, default_value_t = true)]
+    /// Leave out digits
+    #[arg(long = "no-number", action = ArgAction::SetFalse)]
     pub number: bool,
-    #[arg(long, default_value_t = true)]
+    /// Leave out symbols
+    #[arg(long = "no-symbol", action = ArgAction::SetFalse)]
     pub symbol: bool,
+    /// Symbols to use instead of the default `@#$%&*!.?_`, e.g. `-_`
+    #[arg(long, conflicts_with = "symbol", allow_hyphen_values = true)]
+    pub symbols: Option<String>,
+    /// Characters never to use, e.g. `&`
+    #[arg(long, default_value = "", allow_hyphen_values = true)]
+    pub exclude: String,
+    /// Extra characters to draw from, on top of the selected classes
+    #[arg(long, default_value = "", allow_hyphen_values = true)]
+    pub include: String,
+    /// Also leave out `I`, `O` and `1`, which are easy to misread; `l` and `0` are never used
+    #[arg(long)]
+    pub no_ambiguous: bool,
+}
+
+impl Default for CharsetOpts {
+    fn default() -> Self {
+        Self {
+            uppercase: true,
+            lowercase: true,
+            number: true,
+            symbol: true,
+            symbols: None,
+            exclude: String::new(),
+            include: String::new(),
+            no_ambiguous: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+fn parse_report_format(format: &str) -> Result<ReportFormat, anyhow::Error> {
+    format.parse()
+}
+
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(anyhow::anyhow!("Invalid report format: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReportFormat::Text => write!(f, "text"),
+            ReportFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    password: &'a str,
+    #[serde(flatten)]
+    strength: &'a StrengthReport,
 }
 
 impl CmdExector for GenPassOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let password = process_genpass(
-            self.length,
-            self.uppercase,
-            self.lowercase,
-            self.number,
-            self.symbol,
-        )?;
-        print!("{}", password);
+        if let Some(cmd) = self.cmd {
+            return cmd.execute().await;
+        }
+        let (password, strength) = match self.words {
+            Some(words) => process_genphrase(
+                words,
+                &self.separator,
+                self.capitalize,
+                self.digit,
+                self.wordlist.as_deref(),
+                self.min_score,
+            )?,
+            None => process_genpass(self.length, &self.charset, self.min_score)?,
+        };
+        match self.report {
+            ReportFormat::Text => {
+                print!("{}", password);
+                eprintln!("password strength: {}", strength.score);
+                if let Some(entropy) = strength.entropy {
+                    eprintln!("passphrase entropy: {:.1} bits", entropy);
+                }
+            }
+            ReportFormat::Json => {
+                let report = Report {
+                    password: &password,
+                    strength: &strength,
+                };
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            }
+        }
         Ok(())
     }
 }
+
+impl CmdExector for GenPassCheckOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_genpass_check(&self)
+    }
+}
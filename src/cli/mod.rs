@@ -4,6 +4,7 @@ use clap::Parser;
 use enum_dispatch::enum_dispatch;
 
 pub mod base64;
+pub mod convert;
 pub mod csv;
 pub mod genpass;
 pub mod http;
@@ -16,10 +17,20 @@ pub struct Opts {
 }
 #[derive(Debug, Parser)]
 #[enum_dispatch(CmdExector)]
+// parsed once at startup, boxing the variants buys nothing
+#[allow(clippy::large_enum_variant)]
 pub enum SubCommand {
     #[command(name = "csv", about = "Show CSV, or convert to other formats")]
     Csv(csv::CsvOpts),
-    #[command(name = "genpass", about = "Generate a random password")]
+    #[command(
+        name = "convert",
+        about = "Convert between json, yaml, toml and other formats"
+    )]
+    Convert(convert::ConvertOpts),
+    #[command(
+        name = "genpass",
+        about = "Generate a random password, or check existing ones"
+    )]
     GenPass(genpass::GenPassOpts),
     #[command(subcommand)]
     Base64(base64::Base64SubCommand),
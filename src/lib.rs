@@ -2,15 +2,25 @@ mod cli;
 mod process;
 mod utils;
 pub use cli::base64::*;
+pub use cli::convert::*;
+pub use cli::csv::*;
+pub use cli::genpass::*;
 pub use cli::http::*;
 pub use cli::text::*;
 pub use cli::*;
 use enum_dispatch::enum_dispatch;
 pub use process::b64::process_decode;
 pub use process::b64::process_encode;
-pub use process::csv_convert::process_csv;
-pub use process::gen_pass::process_genpass;
+pub use process::convert::process_convert;
+pub use process::csv_codegen::process_csv_codegen;
+pub use process::csv_convert::{convert_csv, process_csv, process_csv_show, process_to_csv};
+pub use process::csv_join::process_csv_join;
+pub use process::csv_split::{process_csv_cat, process_csv_split};
+pub use process::csv_stats::process_csv_stats;
+pub use process::csv_validate::process_csv_validate;
+pub use process::gen_pass::{process_genpass, process_genphrase, StrengthReport};
 pub use process::http_serve::process_http_serve;
+pub use process::pass_check::process_genpass_check;
 pub use process::text::{process_generate, process_sign, process_verify};
 pub use utils::*;
 
@@ -0,0 +1,250 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use crate::cli::csv::{CsvConvertOpts, OutputFormat};
+use crate::process::csv_convert::record_writer;
+use crate::utils::{read_data, write_data};
+
+/// Parse a whole document in `format`. Ndjson becomes an array of its lines.
+pub fn parse_value(bytes: &[u8], format: OutputFormat) -> Result<Value> {
+    Ok(match format {
+        OutputFormat::Json => serde_json::from_slice(bytes)?,
+        OutputFormat::Ndjson => serde_json::Deserializer::from_slice(bytes)
+            .into_iter::<Value>()
+            .collect::<Result<Vec<_>, _>>()?
+            .into(),
+        OutputFormat::Yaml => serde_yaml::from_slice(bytes)?,
+        OutputFormat::Toml => toml_datetimes(toml::from_str(std::str::from_utf8(bytes)?)?),
+        OutputFormat::MsgPack => rmp_serde::from_slice(bytes)?,
+        OutputFormat::Cbor => ciborium::from_reader(bytes)?,
+        OutputFormat::Markdown | OutputFormat::Html => {
+            return Err(anyhow!("Can't read {} input", format))
+        }
+    })
+}
+
+/// Toml datetimes deserialize as `{"$__toml_private_datetime": "…"}`, turn those into
+/// plain strings.
+fn toml_datetimes(value: Value) -> Value {
+    const DATETIME: &str = "$__toml_private_datetime";
+    match value {
+        Value::Object(mut map) if map.len() == 1 && map.contains_key(DATETIME) => {
+            map.remove(DATETIME).unwrap_or_default()
+        }
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, v)| (k, toml_datetimes(v)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(toml_datetimes).collect()),
+        v => v,
+    }
+}
+
+/// Input format named by a file extension, e.g. `config.yml`.
+pub fn format_from_path(path: &str) -> Option<OutputFormat> {
+    match Path::new(path)
+        .extension()?
+        .to_str()?
+        .to_lowercase()
+        .as_str()
+    {
+        "yml" => Some(OutputFormat::Yaml),
+        ext => ext.parse().ok(),
+    }
+}
+
+/// Parse input of unknown format, trying the stricter formats first since almost
+/// any text is valid yaml.
+pub fn parse_any(bytes: &[u8]) -> Result<(OutputFormat, Value)> {
+    let candidates = match std::str::from_utf8(bytes) {
+        Ok(text) if text.trim().is_empty() => return Err(anyhow!("Input is empty")),
+        Ok(_) => [
+            OutputFormat::Json,
+            OutputFormat::Ndjson,
+            OutputFormat::Toml,
+            OutputFormat::Yaml,
+        ]
+        .as_slice(),
+        Err(_) => [OutputFormat::Cbor, OutputFormat::MsgPack].as_slice(),
+    };
+    candidates
+        .iter()
+        .find_map(|&format| parse_value(bytes, format).ok().map(|v| (format, v)))
+        .ok_or_else(|| anyhow!("Can't detect the input format, pass it with --from"))
+}
+
+/// Write `value` as `format`, failing if the format can't represent it.
+pub fn write_value(value: Value, format: OutputFormat, mut writer: impl Write) -> Result<()> {
+    match format {
+        OutputFormat::Json => serde_json::to_writer_pretty(&mut writer, &value)?,
+        OutputFormat::Ndjson => {
+            let items = match value {
+                Value::Array(items) => items,
+                v => vec![v],
+            };
+            for item in items {
+                serde_json::to_writer(&mut writer, &item)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        OutputFormat::Yaml => serde_yaml::to_writer(&mut writer, &value)?,
+        OutputFormat::Toml => {
+            check_toml(&value)?;
+            writer.write_all(toml::to_string(&value)?.as_bytes())?;
+        }
+        OutputFormat::MsgPack => rmp_serde::encode::write(&mut writer, &value)?,
+        OutputFormat::Cbor => ciborium::into_writer(&value, &mut writer)?,
+        OutputFormat::Markdown | OutputFormat::Html => {
+            let records = match value {
+                Value::Array(items) if items.iter().all(Value::is_object) => items,
+                _ => return Err(anyhow!("{} output needs an array of objects", format)),
+            };
+            let mut writer = record_writer(format, writer, &CsvConvertOpts::default());
+            for record in records {
+                writer.write_record(record)?;
+            }
+            return writer.finish();
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn process_convert(
+    input: &str,
+    output: &str,
+    from: Option<OutputFormat>,
+    to: OutputFormat,
+) -> Result<()> {
+    let mut bytes = Vec::new();
+    read_data(input)?.read_to_end(&mut bytes)?;
+    let value = match from.or_else(|| format_from_path(input)) {
+        Some(from) => parse_value(&bytes, from)
+            .map_err(|e| anyhow!("Can't read {} as {}: {}", input, from, e))?,
+        None => parse_any(&bytes)?.1,
+    };
+    write_value(value, to, write_data(output)?)
+}
+
+/// toml needs a table at the top and has no null, nor integers beyond i64.
+fn check_toml(value: &Value) -> Result<()> {
+    let Value::Object(table) = value else {
+        return Err(anyhow!(
+            "toml output needs a table at the top level, not {}",
+            kind(value)
+        ));
+    };
+    for (key, value) in table {
+        check_toml_value(value, key)?;
+    }
+    Ok(())
+}
+
+fn check_toml_value(value: &Value, path: &str) -> Result<()> {
+    match value {
+        Value::Null => Err(anyhow!("`{}` is null, which toml can't represent", path)),
+        Value::Number(n) if n.is_u64() && n.as_i64().is_none() => Err(anyhow!(
+            "`{}` is {}, out of range for a toml integer",
+            path,
+            n
+        )),
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .try_for_each(|(i, v)| check_toml_value(v, &format!("{}[{}]", path, i))),
+        Value::Object(table) => table
+            .iter()
+            .try_for_each(|(k, v)| check_toml_value(v, &format!("{}.{}", path, k))),
+        _ => Ok(()),
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(input: &str, to: OutputFormat) -> Result<String> {
+        let (_, value) = parse_any(input.as_bytes())?;
+        let mut buf = Vec::new();
+        write_value(value, to, &mut buf)?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_convert_keeps_key_order() -> Result<()> {
+        let yaml = "name: wrcli\nversion: 1\nserver:\n  port: 8080\n  host: localhost\n";
+        let toml = convert(yaml, OutputFormat::Toml)?;
+        assert_eq!(
+            toml,
+            "name = \"wrcli\"\nversion = 1\n\n[server]\nport = 8080\nhost = \"localhost\"\n"
+        );
+        let json = convert(&toml, OutputFormat::Json)?;
+        assert_eq!(
+            json,
+            "{\n  \"name\": \"wrcli\",\n  \"version\": 1,\n  \"server\": {\n    \"port\": 8080,\n    \"host\": \"localhost\"\n  }\n}"
+        );
+        assert_eq!(convert(&json, OutputFormat::Yaml)?, yaml);
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_toml_dates() -> Result<()> {
+        let toml = "released = 2024-05-01\n\n[[builds]]\nat = 2024-05-01T12:30:00Z\n";
+        assert_eq!(
+            convert(toml, OutputFormat::Json)?,
+            "{\n  \"released\": \"2024-05-01\",\n  \"builds\": [\n    {\n      \"at\": \"2024-05-01T12:30:00Z\"\n    }\n  ]\n}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_detects_format() -> Result<()> {
+        assert_eq!(parse_any(b"{\"a\": 1}")?.0, OutputFormat::Json);
+        assert_eq!(
+            parse_any(b"{\"a\": 1}\n{\"a\": 2}\n")?.0,
+            OutputFormat::Ndjson
+        );
+        assert_eq!(parse_any(b"[a]\nb = 1\n")?.0, OutputFormat::Toml);
+        assert_eq!(parse_any(b"a:\n  - 1\n")?.0, OutputFormat::Yaml);
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&serde_json::json!({"a": [1, 2]}), &mut cbor)?;
+        assert_eq!(parse_any(&cbor)?.0, OutputFormat::Cbor);
+        assert!(parse_any(b"  \n").is_err());
+        assert_eq!(format_from_path("config.YML"), Some(OutputFormat::Yaml));
+        assert_eq!(format_from_path("data.jsonl"), Some(OutputFormat::Ndjson));
+        assert_eq!(format_from_path("README"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_reports_unrepresentable() {
+        let err = convert("{\"a\": {\"b\": [1, null]}}", OutputFormat::Toml).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`a.b[1]` is null, which toml can't represent"
+        );
+        let err = convert("[1, 2]", OutputFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("not an array"));
+        let err = convert("{\"big\": 18446744073709551615}", OutputFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("out of range"));
+        assert!(convert("{\"a\": 1}", OutputFormat::Markdown).is_err());
+        assert_eq!(
+            convert("[{\"a\": 1}]", OutputFormat::Markdown).unwrap(),
+            "| a |\n| --- |\n| 1 |\n"
+        );
+    }
+}
@@ -0,0 +1,379 @@
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use crate::cli::csv::{CodegenLang, CsvCodegenOpts, CsvReaderOpts, CsvSelectOpts};
+use crate::process::csv_convert::csv_reader;
+use crate::process::csv_select::select_records;
+use crate::process::csv_types::{infer_types, ColumnType, INFER_SAMPLE};
+use crate::utils::{read_data, write_data};
+
+/// A column as seen by the generators.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    pub kind: ColumnType,
+    /// Whether an empty value showed up in the sample
+    pub nullable: bool,
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while",
+    "abstract", "become", "box", "do", "final", "gen", "macro", "override", "priv", "try",
+    "typeof", "unsized", "virtual", "yield",
+];
+
+/// Infer column types and nullability from a sample of the records.
+pub fn infer_columns(
+    reader: impl Read,
+    reader_opts: &CsvReaderOpts,
+    select_opts: &CsvSelectOpts,
+) -> Result<Vec<Column>> {
+    let reader = csv_reader(reader, reader_opts)?;
+    let (header, records) = select_records(reader, reader_opts, select_opts)?;
+    let sample = records.take(INFER_SAMPLE).collect::<Result<Vec<_>>>()?;
+    let types = infer_types(&sample, header.len());
+    Ok(header
+        .iter()
+        .zip(types)
+        .enumerate()
+        .map(|(i, (name, kind))| Column {
+            name: name.to_string(),
+            kind,
+            nullable: sample
+                .iter()
+                .any(|r| r.get(i).unwrap_or_default().trim().is_empty()),
+        })
+        .collect())
+}
+
+/// A serde struct that deserializes the csv records, e.g. with `csv::Reader::deserialize`.
+pub fn rust_struct(name: &str, columns: &[Column]) -> String {
+    let fields = unique_fields(columns);
+    // pick the serde casing that leaves the fewest fields needing their own rename
+    let rename_all = [None, Some("PascalCase"), Some("camelCase")]
+        .into_iter()
+        .min_by_key(|case| {
+            columns
+                .iter()
+                .zip(&fields)
+                .filter(|(c, f)| serde_name(f, *case) != c.name)
+                .count()
+        })
+        .flatten();
+
+    let mut out = String::from("use serde::{Deserialize, Serialize};\n\n");
+    out.push_str("#[derive(Debug, Deserialize, Serialize)]\n");
+    if let Some(case) = rename_all {
+        let _ = writeln!(out, "#[serde(rename_all = \"{}\")]", case);
+    }
+    let _ = writeln!(out, "pub struct {} {{", type_name(name));
+    for (column, field) in columns.iter().zip(&fields) {
+        if serde_name(field, rename_all) != column.name {
+            let _ = writeln!(out, "    #[serde(rename = {:?})]", column.name);
+        }
+        let kind = match column.kind {
+            ColumnType::Bool => "bool",
+            ColumnType::Integer => "i64",
+            ColumnType::Float => "f64",
+            ColumnType::Null | ColumnType::Date | ColumnType::String => "String",
+        };
+        let kind = if column.nullable {
+            format!("Option<{}>", kind)
+        } else {
+            kind.to_string()
+        };
+        let _ = writeln!(out, "    pub {}: {},", rust_ident(field), kind);
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// A TypeScript interface for the records `csv --infer-types` emits.
+pub fn typescript_interface(name: &str, columns: &[Column]) -> String {
+    let mut out = format!("export interface {} {{\n", type_name(name));
+    for column in columns {
+        let kind = match column.kind {
+            ColumnType::Bool => "boolean",
+            ColumnType::Integer | ColumnType::Float => "number",
+            ColumnType::Null | ColumnType::Date | ColumnType::String => "string",
+        };
+        let null = if column.nullable { " | null" } else { "" };
+        let _ = writeln!(out, "  {}: {}{};", ts_key(&column.name), kind, null);
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// A JSON Schema for the records, usable with `csv validate --schema`.
+pub fn json_schema(name: &str, columns: &[Column]) -> Result<String> {
+    let mut properties = Map::new();
+    for column in columns {
+        let kind = match column.kind {
+            ColumnType::Bool => "boolean",
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "number",
+            ColumnType::Null | ColumnType::Date | ColumnType::String => "string",
+        };
+        let mut property = Map::new();
+        property.insert(
+            "type".into(),
+            if column.nullable {
+                json!([kind, "null"])
+            } else {
+                json!(kind)
+            },
+        );
+        if column.kind == ColumnType::Date {
+            property.insert("format".into(), json!("date"));
+        }
+        properties.insert(column.name.clone(), Value::Object(property));
+    }
+    let required: Vec<&str> = columns
+        .iter()
+        .filter(|c| !c.nullable)
+        .map(|c| c.name.as_str())
+        .collect();
+    let schema = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": type_name(name),
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    });
+    Ok(serde_json::to_string_pretty(&schema)? + "\n")
+}
+
+pub fn process_csv_codegen(opts: &CsvCodegenOpts) -> Result<()> {
+    let columns = infer_columns(read_data(&opts.input)?, &opts.reader, &opts.select)?;
+    let name = match &opts.name {
+        Some(name) => name.clone(),
+        None if opts.input == "-" => "Record".into(),
+        None => Path::new(&opts.input)
+            .file_stem()
+            .map_or("Record".into(), |s| s.to_string_lossy().into_owned()),
+    };
+    let code = match opts.lang {
+        CodegenLang::Rust => rust_struct(&name, &columns),
+        CodegenLang::TypeScript => typescript_interface(&name, &columns),
+        CodegenLang::JsonSchema => json_schema(&name, &columns)?,
+    };
+    let mut writer = write_data(&opts.output)?;
+    writer.write_all(code.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Split a header into lowercase words, breaking on punctuation and case changes.
+fn words(s: &str) -> Vec<String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_ascii_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        let prev = i.checked_sub(1).map(|p| chars[p]);
+        let next = chars.get(i + 1);
+        // `kitNumber` and `HTTPServer` both split before the last capital
+        let boundary = c.is_ascii_uppercase()
+            && prev.is_some_and(|p| {
+                p.is_ascii_lowercase()
+                    || p.is_ascii_digit()
+                    || (p.is_ascii_uppercase() && next.is_some_and(char::is_ascii_lowercase))
+            });
+        if boundary && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        word.push(c.to_ascii_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// snake_case field names, deduplicated and never empty.
+fn unique_fields(columns: &[Column]) -> Vec<String> {
+    let mut fields: Vec<String> = Vec::with_capacity(columns.len());
+    for (i, column) in columns.iter().enumerate() {
+        let mut field = words(&column.name).join("_");
+        if field.is_empty() {
+            field = format!("field_{}", i);
+        } else if field.starts_with(|c: char| c.is_ascii_digit()) {
+            field = format!("field_{}", field);
+        }
+        let base = field.clone();
+        let mut n = i;
+        while fields.contains(&field) {
+            field = format!("{}_{}", base, n);
+            n += 1;
+        }
+        fields.push(field);
+    }
+    fields
+}
+
+/// The name serde gives a snake_case field under `rename_all`.
+fn serde_case(field: &str, case: Option<&str>) -> String {
+    let pascal = || -> String {
+        field
+            .split('_')
+            .map(|w| {
+                let mut chars = w.chars();
+                chars.next().map_or(String::new(), |c| {
+                    c.to_ascii_uppercase().to_string() + chars.as_str()
+                })
+            })
+            .collect()
+    };
+    match case {
+        Some("PascalCase") => pascal(),
+        Some("camelCase") => {
+            let pascal = pascal();
+            let mut chars = pascal.chars();
+            chars.next().map_or(String::new(), |c| {
+                c.to_ascii_lowercase().to_string() + chars.as_str()
+            })
+        }
+        _ => field.to_string(),
+    }
+}
+
+/// The name serde gives the emitted identifier of `field`, which only drops an `r#`.
+fn serde_name(field: &str, case: Option<&str>) -> String {
+    let ident = rust_ident(field);
+    serde_case(ident.trim_start_matches("r#"), case)
+}
+
+fn rust_ident(field: &str) -> String {
+    match field {
+        // these can't be raw identifiers
+        "self" | "super" | "crate" => format!("{}_", field),
+        f if RUST_KEYWORDS.contains(&f) => format!("r#{}", f),
+        f => f.to_string(),
+    }
+}
+
+fn type_name(name: &str) -> String {
+    let name = serde_case(&words(name).join("_"), Some("PascalCase"));
+    match name.chars().next() {
+        None => "Record".into(),
+        Some(c) if c.is_ascii_digit() => format!("Record{}", name),
+        Some(_) => name,
+    }
+}
+
+fn ts_key(name: &str) -> String {
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if valid {
+        name.to_string()
+    } else {
+        Value::from(name).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+
+    fn juventus() -> Vec<Column> {
+        let reader = File::open("assets/juventus.csv").unwrap();
+        infer_columns(reader, &CsvReaderOpts::default(), &CsvSelectOpts::default()).unwrap()
+    }
+
+    #[test]
+    fn test_codegen_snapshots() {
+        let columns = juventus();
+        assert_eq!(
+            rust_struct("juventus", &columns),
+            fs::read_to_string("fixtures/snapshots/juventus.rs").unwrap()
+        );
+        assert_eq!(
+            typescript_interface("juventus", &columns),
+            fs::read_to_string("fixtures/snapshots/juventus.ts").unwrap()
+        );
+        assert_eq!(
+            json_schema("juventus", &columns).unwrap(),
+            fs::read_to_string("fixtures/snapshots/juventus.schema.json").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_codegen_identifiers() {
+        let names = [
+            "kitNumber",
+            "HTTPServer",
+            "type",
+            "2nd Team",
+            "",
+            "self",
+            "Kit Number",
+        ];
+        let columns: Vec<Column> = names
+            .iter()
+            .map(|name| Column {
+                name: name.to_string(),
+                kind: ColumnType::Float,
+                nullable: true,
+            })
+            .collect();
+        let code = rust_struct("my-export 2019", &columns);
+        assert!(code.contains("pub struct MyExport2019 {"));
+        assert!(code.contains("#[serde(rename_all = \"camelCase\")]"));
+        assert!(code.contains("    pub kit_number: Option<f64>,"));
+        assert!(code.contains("    #[serde(rename = \"HTTPServer\")]\n    pub http_server:"));
+        // serde strips `r#`, and camelCase drops the trailing `_`, so these keep their names
+        assert!(code.contains("    pub r#type: Option<f64>,"));
+        assert!(code.contains("    pub self_: Option<f64>,"));
+        assert!(code.contains("    #[serde(rename = \"2nd Team\")]\n    pub field_2nd_team:"));
+        assert!(code.contains("    #[serde(rename = \"\")]\n    pub field_4:"));
+        assert!(code.contains("    #[serde(rename = \"Kit Number\")]\n    pub kit_number_6:"));
+
+        let ts = typescript_interface("x", &columns);
+        assert!(ts.contains("  kitNumber: number | null;"));
+        assert!(ts.contains("  \"2nd Team\": number | null;"));
+
+        // a suffixed duplicate can't collide with a real column of that name
+        let columns: Vec<Column> = ["a", "a_2", "a"]
+            .iter()
+            .map(|name| Column {
+                name: name.to_string(),
+                kind: ColumnType::Integer,
+                nullable: false,
+            })
+            .collect();
+        assert_eq!(unique_fields(&columns), ["a", "a_2", "a_3"]);
+        let code = rust_struct("x", &columns);
+        assert_eq!(code.matches("pub a_2: i64").count(), 1);
+
+        // without rename_all serde keeps the `_` of `self_`, so it needs its own rename
+        let columns: Vec<Column> = ["id", "self", "type", "kit_number"]
+            .iter()
+            .map(|name| Column {
+                name: name.to_string(),
+                kind: ColumnType::String,
+                nullable: false,
+            })
+            .collect();
+        let code = rust_struct("x", &columns);
+        assert!(!code.contains("rename_all"));
+        assert!(code.contains("    #[serde(rename = \"self\")]\n    pub self_: String,"));
+        assert!(code.contains("    pub id: String,\n    #"));
+        assert!(code.contains("String,\n    pub r#type: String,"));
+    }
+}
@@ -1,38 +1,988 @@
-use anyhow::Result;
-use csv::Reader;
-use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+use chardetng::EncodingDetector;
+use csv::{Reader, ReaderBuilder, StringRecord, Writer, WriterBuilder};
+use encoding_rs::{Encoding, UTF_8};
+use encoding_rs_io::DecodeReaderBytesBuilder;
 use serde_json::Value;
-use std::fs;
-
-use crate::cli::csv::OutputFormat;
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "PascalCase")]
-struct Player {
-    name: String,
-    position: String,
-    #[serde(rename = "DOB")]
-    dob: String,
-    nationality: String,
-    #[serde(rename = "Kit Number")]
-    kit: u8,
-}
-
-pub fn process_csv(input: &str, output: &str, format: OutputFormat) -> Result<()> {
-    let mut reader = Reader::from_path(input)?;
-    let mut ret = Vec::with_capacity(256);
-    let header = reader.headers()?.clone();
-    for item in reader.records() {
-        let record = item?;
-        let json_value = header.iter().zip(record.iter()).collect::<Value>();
-        // println!("{:?}", record);
-        ret.push(json_value);
-    }
-    let contents = match format {
-        OutputFormat::Json => serde_json::to_string_pretty(&ret)?,
-        OutputFormat::Yaml => serde_yaml::to_string(&ret)?,
-        OutputFormat::Toml => toml::to_string(&ret)?,
+use std::io::{Cursor, Read, Write};
+
+use crate::cli::csv::{
+    CsvConvertOpts, CsvReaderOpts, CsvSelectOpts, CsvShowOpts, InputEncoding, OutputFormat,
+};
+use crate::process::convert::parse_value;
+use crate::process::csv_nested::{flatten, unflatten};
+use crate::process::csv_select::{select_records, Records};
+use crate::process::csv_types::{infer_types, ColumnType, INFER_SAMPLE};
+use crate::process::table::{render_table, TableStyle};
+use crate::utils::{read_data, write_data};
+
+/// How much of the input `--encoding auto` looks at before picking an encoding.
+const SNIFF_BYTES: usize = 64 * 1024;
+
+/// Build a csv reader honoring the encoding, delimiter and header options.
+pub fn csv_reader<'a, R: Read + 'a>(
+    reader: R,
+    opts: &CsvReaderOpts,
+) -> Result<Reader<Box<dyn Read + 'a>>> {
+    Ok(ReaderBuilder::new()
+        .delimiter(opts.delimiter)
+        .has_headers(opts.header)
+        .from_reader(decode(reader, opts.encoding)?))
+}
+
+/// Transcode the input to utf-8, dropping any byte order mark. A BOM wins over the
+/// requested encoding, and valid utf-8 is passed through untouched.
+fn decode<'a, R: Read + 'a>(mut reader: R, encoding: InputEncoding) -> Result<Box<dyn Read + 'a>> {
+    let (encoding, reader): (_, Box<dyn Read + 'a>) = match encoding {
+        InputEncoding::Fixed(encoding) => (encoding, Box::new(reader)),
+        InputEncoding::Auto => {
+            let mut prefix = Vec::new();
+            reader
+                .by_ref()
+                .take(SNIFF_BYTES as u64)
+                .read_to_end(&mut prefix)?;
+            (
+                detect_encoding(&prefix),
+                Box::new(Cursor::new(prefix).chain(reader)),
+            )
+        }
+    };
+    // an explicit utf-8 decoder would replace invalid bytes, leave those for csv to report
+    let encoding = Some(encoding).filter(|e| *e != UTF_8);
+    Ok(Box::new(
+        DecodeReaderBytesBuilder::new()
+            .encoding(encoding)
+            .strip_bom(true)
+            .bom_override(true)
+            .utf8_passthru(true)
+            .build(reader),
+    ))
+}
+
+/// Guess the encoding of the start of a file: BOM first, then utf-8, then chardetng.
+pub fn detect_encoding(prefix: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(prefix) {
+        return encoding;
+    }
+    match std::str::from_utf8(prefix) {
+        // a character cut in half by the sniff window still counts as utf-8
+        Err(e) if e.error_len().is_some() => {
+            let mut detector = EncodingDetector::new();
+            detector.feed(prefix, prefix.len() < SNIFF_BYTES);
+            detector.guess(None, false)
+        }
+        _ => UTF_8,
+    }
+}
+
+/// Build a csv writer using the same delimiter as the reader.
+pub fn csv_writer<W: Write>(writer: W, opts: &CsvReaderOpts) -> Writer<W> {
+    WriterBuilder::new()
+        .delimiter(opts.delimiter)
+        .from_writer(writer)
+}
+
+/// Return the header row, or `col0`, `col1`, ... when the input has no header.
+pub fn csv_headers<R: Read>(reader: &mut Reader<R>, opts: &CsvReaderOpts) -> Result<StringRecord> {
+    let first = reader.headers()?;
+    if opts.header {
+        Ok(first.clone())
+    } else {
+        Ok((0..first.len()).map(|i| format!("col{}", i)).collect())
+    }
+}
+
+/// Sink for converted records, text or binary. Streaming formats write each record
+/// as soon as it arrives, the others buffer until `finish`.
+pub trait RecordWriter {
+    fn write_record(&mut self, record: Value) -> Result<()>;
+    fn finish(self: Box<Self>) -> Result<()>;
+
+    /// Announce the columns when no record will arrive, so tables can still
+    /// render their header.
+    fn write_header(&mut self, _columns: Vec<String>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes a pretty json array, one record at a time.
+struct JsonWriter<W: Write> {
+    writer: W,
+    count: usize,
+}
+
+/// Writes newline delimited json, one record per line.
+struct NdjsonWriter<W: Write> {
+    writer: W,
+}
+
+/// Writes a markdown table, columns taken from the first record or the csv header.
+struct MarkdownWriter<W: Write> {
+    writer: W,
+    columns: Option<Vec<String>>,
+}
+
+/// Writes an html table, columns taken from the first record or the csv header.
+struct HtmlWriter<W: Write> {
+    writer: W,
+    columns: Option<Vec<String>>,
+}
+
+/// Writes a cbor array of indefinite length, so records can be streamed.
+struct CborWriter<W: Write> {
+    writer: W,
+    started: bool,
+}
+
+/// Collects every record and serializes them in one go.
+struct BufferedWriter<W: Write> {
+    writer: W,
+    format: OutputFormat,
+    records: Vec<Value>,
+    toml_key: String,
+}
+
+impl<W: Write> RecordWriter for JsonWriter<W> {
+    fn write_record(&mut self, record: Value) -> Result<()> {
+        let sep = if self.count == 0 { "[\n  " } else { ",\n  " };
+        // indent the pretty record by one level so the output matches `to_string_pretty`
+        let record = serde_json::to_string_pretty(&record)?.replace('\n', "\n  ");
+        self.writer.write_all(sep.as_bytes())?;
+        self.writer.write_all(record.as_bytes())?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        let end = if self.count == 0 { "[]" } else { "\n]" };
+        self.writer.write_all(end.as_bytes())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for NdjsonWriter<W> {
+    fn write_record(&mut self, record: Value) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, &record)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for MarkdownWriter<W> {
+    fn write_record(&mut self, record: Value) -> Result<()> {
+        self.write_header(record_keys(&record))?;
+        let columns = self.columns.as_deref().unwrap_or_default();
+        let cells: Vec<String> = columns
+            .iter()
+            .map(|c| escape_markdown(&cell_text(record.get(c))))
+            .collect();
+        writeln!(self.writer, "| {} |", cells.join(" | "))?;
+        Ok(())
+    }
+
+    fn write_header(&mut self, columns: Vec<String>) -> Result<()> {
+        if self.columns.is_none() {
+            let header: Vec<String> = columns.iter().map(|c| escape_markdown(c)).collect();
+            writeln!(self.writer, "| {} |", header.join(" | "))?;
+            writeln!(self.writer, "|{}", " --- |".repeat(columns.len()))?;
+            self.columns = Some(columns);
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for HtmlWriter<W> {
+    fn write_record(&mut self, record: Value) -> Result<()> {
+        self.write_header(record_keys(&record))?;
+        let columns = self.columns.as_deref().unwrap_or_default();
+        writeln!(self.writer, "    <tr>")?;
+        for c in columns {
+            let cell = escape_html(&cell_text(record.get(c)));
+            writeln!(self.writer, "      <td>{}</td>", cell)?;
+        }
+        writeln!(self.writer, "    </tr>")?;
+        Ok(())
+    }
+
+    fn write_header(&mut self, columns: Vec<String>) -> Result<()> {
+        if self.columns.is_none() {
+            writeln!(self.writer, "<table>\n  <thead>\n    <tr>")?;
+            for c in &columns {
+                writeln!(self.writer, "      <th>{}</th>", escape_html(c))?;
+            }
+            writeln!(self.writer, "    </tr>\n  </thead>\n  <tbody>")?;
+            self.columns = Some(columns);
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        if self.columns.is_some() {
+            writeln!(self.writer, "  </tbody>\n</table>")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for BufferedWriter<W> {
+    fn write_record(&mut self, record: Value) -> Result<()> {
+        self.records.push(record);
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        match self.format {
+            OutputFormat::Yaml => serde_yaml::to_writer(&mut self.writer, &self.records)?,
+            OutputFormat::Toml => {
+                // toml has no top-level array, so nest the records as an array of tables
+                let records = self.records.drain(..).map(strip_nulls).collect();
+                let doc =
+                    serde_json::Map::from_iter([(self.toml_key.clone(), Value::Array(records))]);
+                self.writer.write_all(toml::to_string(&doc)?.as_bytes())?;
+            }
+            // msgpack arrays carry their length up front
+            OutputFormat::MsgPack => rmp_serde::encode::write(&mut self.writer, &self.records)?,
+            _ => unreachable!("{} is streamed", self.format),
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for CborWriter<W> {
+    fn write_record(&mut self, record: Value) -> Result<()> {
+        if !self.started {
+            // start of an indefinite length array
+            self.writer.write_all(&[0x9f])?;
+            self.started = true;
+        }
+        ciborium::into_writer(&record, &mut self.writer)?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        if self.started {
+            // break, closing the array
+            self.writer.write_all(&[0xff])?;
+        } else {
+            // an empty definite length array
+            self.writer.write_all(&[0x80])?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+pub fn record_writer<'a, W: Write + 'a>(
+    format: OutputFormat,
+    writer: W,
+    opts: &CsvConvertOpts,
+) -> Box<dyn RecordWriter + 'a> {
+    match format {
+        OutputFormat::Json => Box::new(JsonWriter { writer, count: 0 }),
+        OutputFormat::Ndjson => Box::new(NdjsonWriter { writer }),
+        OutputFormat::Markdown => Box::new(MarkdownWriter {
+            writer,
+            columns: None,
+        }),
+        OutputFormat::Html => Box::new(HtmlWriter {
+            writer,
+            columns: None,
+        }),
+        OutputFormat::Cbor => Box::new(CborWriter {
+            writer,
+            started: false,
+        }),
+        OutputFormat::Yaml | OutputFormat::Toml | OutputFormat::MsgPack => {
+            Box::new(BufferedWriter {
+                writer,
+                format,
+                records: Vec::with_capacity(256),
+                toml_key: opts.toml_key.clone(),
+            })
+        }
+    }
+}
+
+/// Convert csv from `reader` into `format` on `writer`. Json, ndjson, markdown, html
+/// and cbor are streamed record by record so memory stays flat regardless of input size.
+pub fn convert_csv(
+    reader: impl Read,
+    writer: impl Write,
+    format: OutputFormat,
+    reader_opts: &CsvReaderOpts,
+    select_opts: &CsvSelectOpts,
+    opts: &CsvConvertOpts,
+) -> Result<()> {
+    let reader = csv_reader(reader, reader_opts)?;
+    let (header, records) = select_records(reader, reader_opts, select_opts)?;
+    write_records(&header, records, writer, format, opts)
+}
+
+/// Write records through the writer for `format`, inferring column types first
+/// when asked.
+pub fn write_records(
+    header: &StringRecord,
+    mut records: Records<'_>,
+    writer: impl Write,
+    format: OutputFormat,
+    opts: &CsvConvertOpts,
+) -> Result<()> {
+    let mut writer = record_writer(format, writer, opts);
+    // column types are decided from a bounded sample, which is then replayed
+    let mut sample = Vec::new();
+    let types = if opts.infer_types {
+        for item in records.by_ref().take(INFER_SAMPLE) {
+            sample.push(item?);
+        }
+        Some(infer_types(&sample, header.len()))
+    } else {
+        None
+    };
+    let to_record = |record: &StringRecord| -> Result<Value> {
+        let value = to_value(header, record, types.as_deref());
+        if opts.unflatten {
+            unflatten(value)
+        } else {
+            Ok(value)
+        }
+    };
+    let mut empty = true;
+    for item in sample.into_iter().map(Ok).chain(records) {
+        writer.write_record(to_record(&item?)?)?;
+        empty = false;
+    }
+    if empty {
+        // a blank row gives the same columns a real one would
+        let blank: StringRecord = header.iter().map(|_| "").collect();
+        writer.write_header(record_keys(&to_record(&blank)?))?;
+    }
+    writer.finish()
+}
+
+fn record_keys(record: &Value) -> Vec<String> {
+    record
+        .as_object()
+        .map(|m| m.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Plain text of a value for table-like outputs: strings unquoted, null empty.
+fn cell_text(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+    }
+}
+
+fn escape_markdown(cell: &str) -> String {
+    cell.replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace('\n', "<br>")
+}
+
+fn escape_html(cell: &str) -> String {
+    cell.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Toml can't represent null, so drop null fields and array items.
+fn strip_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => map
+            .into_iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, v)| (k, strip_nulls(v)))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        Value::Array(items) => items
+            .into_iter()
+            .filter(|v| !v.is_null())
+            .map(strip_nulls)
+            .collect(),
+        v => v,
+    }
+}
+
+fn to_value(header: &StringRecord, record: &StringRecord, types: Option<&[ColumnType]>) -> Value {
+    match types {
+        Some(types) => header
+            .iter()
+            .zip(record.iter())
+            .zip(types)
+            .map(|((key, field), t)| (key.to_string(), t.parse(field)))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        None => header.iter().zip(record.iter()).collect(),
+    }
+}
+
+pub fn process_csv(
+    input: &str,
+    output: &str,
+    format: OutputFormat,
+    reader_opts: &CsvReaderOpts,
+    select_opts: &CsvSelectOpts,
+    opts: &CsvConvertOpts,
+) -> Result<()> {
+    let reader = read_data(input)?;
+    let writer = write_data(output)?;
+    convert_csv(reader, writer, format, reader_opts, select_opts, opts)
+}
+
+/// Render a page of csv records as a table.
+pub fn process_csv_show(
+    input: &str,
+    reader_opts: &CsvReaderOpts,
+    select_opts: &CsvSelectOpts,
+    opts: &CsvShowOpts,
+    style: TableStyle,
+) -> Result<String> {
+    let reader = csv_reader(read_data(input)?, reader_opts)?;
+    let (header, records) = select_records(reader, reader_opts, select_opts)?;
+    let header: Vec<String> = header.iter().map(String::from).collect();
+    let rows = records
+        .skip(opts.offset)
+        .take(opts.limit.unwrap_or(usize::MAX))
+        .map(|item| Ok(item?.iter().map(String::from).collect()))
+        .collect::<Result<Vec<Vec<String>>>>()?;
+    Ok(render_table(&header, &rows, style, opts.max_width))
+}
+
+/// Convert an array of objects in json/yaml/toml back into csv.
+pub fn process_to_csv(
+    input: &str,
+    output: &str,
+    from: OutputFormat,
+    opts: &CsvReaderOpts,
+    flat: bool,
+) -> Result<()> {
+    let mut reader = read_data(input)?;
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    let mut records = parse_records(&buf, from)?;
+    if flat {
+        records = records.into_iter().map(flatten).collect();
+    }
+    write_csv(&records, write_data(output)?, opts)
+}
+
+fn parse_records(contents: impl AsRef<[u8]>, from: OutputFormat) -> Result<Vec<Value>> {
+    let value = match (from, parse_value(contents.as_ref(), from)?) {
+        // toml has no top-level array, so take the only array of tables in the document
+        (OutputFormat::Toml, Value::Object(table)) => {
+            let mut arrays = table.into_iter().filter(|(_, v)| v.is_array());
+            match (arrays.next(), arrays.next()) {
+                (Some((_, v)), None) => v,
+                _ => {
+                    return Err(anyhow!(
+                        "Expected exactly one array of tables in toml input"
+                    ))
+                }
+            }
+        }
+        (_, value) => value,
     };
-    // let csv_to_json = serde_json::to_string_pretty(&ret)?;
-    fs::write(output, contents)?;
+    match value {
+        Value::Array(records) if records.iter().all(Value::is_object) => Ok(records),
+        _ => Err(anyhow!("Expected an array of objects")),
+    }
+}
+
+/// Union the keys of all records in first-seen order.
+fn union_keys(records: &[Value]) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    for record in records.iter().filter_map(Value::as_object) {
+        for key in record.keys() {
+            if !keys.contains(key) {
+                keys.push(key.clone());
+            }
+        }
+    }
+    keys
+}
+
+fn write_csv(records: &[Value], writer: impl Write, opts: &CsvReaderOpts) -> Result<()> {
+    let header = union_keys(records);
+    let mut writer = csv_writer(writer, opts);
+    if opts.header {
+        writer.write_record(&header)?;
+    }
+    for record in records {
+        let row = header.iter().map(|key| cell_text(record.get(key)));
+        writer.write_record(row)?;
+    }
+    writer.flush()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+
+    fn read_records(input: &str, opts: &CsvReaderOpts) -> Result<Vec<Value>> {
+        let mut buf = Vec::new();
+        let convert_opts = CsvConvertOpts::default();
+        convert_csv(
+            File::open(input)?,
+            &mut buf,
+            OutputFormat::Ndjson,
+            opts,
+            &CsvSelectOpts::default(),
+            &convert_opts,
+        )?;
+        let ret = serde_json::Deserializer::from_slice(&buf).into_iter::<Value>();
+        Ok(ret.collect::<Result<_, _>>()?)
+    }
+
+    #[test]
+    fn test_json_writer_matches_pretty() -> Result<()> {
+        let mut buf = Vec::new();
+        let opts = CsvReaderOpts::default();
+        let convert_opts = CsvConvertOpts::default();
+        let input = File::open("assets/juventus.csv")?;
+        convert_csv(
+            input,
+            &mut buf,
+            OutputFormat::Json,
+            &opts,
+            &CsvSelectOpts::default(),
+            &convert_opts,
+        )?;
+        let records = read_records("assets/juventus.csv", &opts)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            serde_json::to_string_pretty(&records)?
+        );
+
+        let mut buf = Vec::new();
+        convert_csv(
+            &b"a,b\n"[..],
+            &mut buf,
+            OutputFormat::Json,
+            &opts,
+            &CsvSelectOpts::default(),
+            &convert_opts,
+        )?;
+        assert_eq!(buf, b"[]");
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_records_with_header() -> Result<()> {
+        let ret = read_records("assets/juventus.csv", &CsvReaderOpts::default())?;
+        assert_eq!(ret.len(), 27);
+        assert_eq!(ret[0]["Name"], "Wojciech Szczesny");
+        assert_eq!(ret[0]["Kit Number"], "1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_csv_infer_types() -> Result<()> {
+        let convert_opts = CsvConvertOpts {
+            infer_types: true,
+            ..Default::default()
+        };
+        let opts = CsvReaderOpts::default();
+        let mut buf = Vec::new();
+        let input = File::open("assets/juventus.csv")?;
+        convert_csv(
+            input,
+            &mut buf,
+            OutputFormat::Yaml,
+            &opts,
+            &CsvSelectOpts::default(),
+            &convert_opts,
+        )?;
+        let records: Vec<Value> = serde_yaml::from_slice(&buf)?;
+        assert_eq!(records[0]["Kit Number"], 1);
+        assert_eq!(records[0]["DOB"], "Apr 18, 1990 (29)");
+
+        let input = &b"id,score,active,note\n1,9.5,true,\n2,,false,x\n"[..];
+        let mut buf = Vec::new();
+        convert_csv(
+            input,
+            &mut buf,
+            OutputFormat::Json,
+            &opts,
+            &CsvSelectOpts::default(),
+            &convert_opts,
+        )?;
+        let records: Vec<Value> = serde_json::from_slice(&buf)?;
+        assert_eq!(records[0]["score"], 9.5);
+        assert_eq!(records[0]["active"], true);
+        assert_eq!(records[0]["note"], "");
+        assert_eq!(records[1]["score"], Value::Null);
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_round_trip() -> Result<()> {
+        let opts = CsvReaderOpts::default();
+        let convert_opts = CsvConvertOpts {
+            infer_types: true,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        let input = File::open("assets/juventus.csv")?;
+        convert_csv(
+            input,
+            &mut buf,
+            OutputFormat::Toml,
+            &opts,
+            &CsvSelectOpts::default(),
+            &convert_opts,
+        )?;
+        let toml = String::from_utf8(buf)?;
+        assert!(toml.starts_with("[[records]]\n"));
+
+        let doc: serde_json::Map<String, Value> = toml::from_str(&toml)?;
+        assert_eq!(doc["records"].as_array().map(Vec::len), Some(27));
+        assert_eq!(doc["records"][0]["Kit Number"], 1);
+
+        let mut csv = Vec::new();
+        write_csv(&parse_records(&toml, OutputFormat::Toml)?, &mut csv, &opts)?;
+        assert_eq!(
+            String::from_utf8(csv)?,
+            fs::read_to_string("assets/juventus.csv")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_drops_nulls() -> Result<()> {
+        let opts = CsvReaderOpts::default();
+        let convert_opts = CsvConvertOpts {
+            infer_types: true,
+            toml_key: "players".into(),
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        let input = &b"name,kit\nDanilo,13\nRamsey,\n"[..];
+        convert_csv(
+            input,
+            &mut buf,
+            OutputFormat::Toml,
+            &opts,
+            &CsvSelectOpts::default(),
+            &convert_opts,
+        )?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "[[players]]\nname = \"Danilo\"\nkit = 13\n\n[[players]]\nname = \"Ramsey\"\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_show_pages() -> Result<()> {
+        let opts = CsvShowOpts {
+            show: true,
+            limit: Some(2),
+            offset: 1,
+            max_width: 12,
+        };
+        let table = process_csv_show(
+            "assets/juventus.csv",
+            &CsvReaderOpts::default(),
+            &CsvSelectOpts::default(),
+            &opts,
+            TableStyle::Plain,
+        )?;
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("Name          Position"));
+        assert!(lines[1].starts_with("Mattia Perin  Goalkeeper"));
+        assert!(lines[2].starts_with("Gianluigi B…  Goalkeeper"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshots() -> Result<()> {
+        let opts = CsvReaderOpts {
+            delimiter: b'\t',
+            ..Default::default()
+        };
+        let convert_opts = CsvConvertOpts::default();
+        for (format, snapshot) in [
+            (OutputFormat::Ndjson, "fixtures/snapshots/players.ndjson"),
+            (OutputFormat::Markdown, "fixtures/snapshots/players.md"),
+            (OutputFormat::Html, "fixtures/snapshots/players.html"),
+        ] {
+            let mut buf = Vec::new();
+            let input = File::open("fixtures/players.tsv")?;
+            convert_csv(
+                input,
+                &mut buf,
+                format,
+                &opts,
+                &CsvSelectOpts::default(),
+                &convert_opts,
+            )?;
+            assert_eq!(
+                String::from_utf8(buf)?,
+                fs::read_to_string(snapshot)?,
+                "{format}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_tables_keep_header() -> Result<()> {
+        let convert = |input: &str, format, unflatten| -> Result<String> {
+            let mut buf = Vec::new();
+            let opts = CsvConvertOpts {
+                unflatten,
+                ..Default::default()
+            };
+            convert_csv(
+                input.as_bytes(),
+                &mut buf,
+                format,
+                &CsvReaderOpts::default(),
+                &CsvSelectOpts::default(),
+                &opts,
+            )?;
+            Ok(String::from_utf8(buf)?)
+        };
+        assert_eq!(
+            convert("a,b\n", OutputFormat::Markdown, false)?,
+            "| a | b |\n| --- | --- |\n"
+        );
+        assert_eq!(
+            convert("id,user.name,user.age\n", OutputFormat::Markdown, true)?,
+            "| id | user |\n| --- | --- |\n"
+        );
+        let html = convert("a,b\n", OutputFormat::Html, false)?;
+        assert!(html.contains("<th>a</th>") && html.contains("<th>b</th>"));
+        assert!(html.ends_with("<tbody>\n  </tbody>\n</table>\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_table_cells() {
+        assert_eq!(escape_markdown("a|b\nc"), "a\\|b<br>c");
+        assert_eq!(
+            escape_html("<b>Tom & \"Jerry\"</b>"),
+            "&lt;b&gt;Tom &amp; &quot;Jerry&quot;&lt;/b&gt;"
+        );
+    }
+
+    #[test]
+    fn test_convert_csv_select() -> Result<()> {
+        let select_opts = CsvSelectOpts {
+            select: vec!["Kit Number".into(), "Name".into()],
+            filter: Some("Position == 'Goalkeeper' && `Kit Number` > 30".into()),
+            rename: vec![("Kit Number".into(), "kit".into())],
+            ..Default::default()
+        };
+        let convert_opts = CsvConvertOpts {
+            infer_types: true,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        let input = File::open("assets/juventus.csv")?;
+        let opts = CsvReaderOpts::default();
+        convert_csv(
+            input,
+            &mut buf,
+            OutputFormat::Ndjson,
+            &opts,
+            &select_opts,
+            &convert_opts,
+        )?;
+        let lines: Vec<String> = String::from_utf8(buf)?.lines().map(String::from).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], r#"{"kit":37,"Name":"Mattia Perin"}"#);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_records_tsv() -> Result<()> {
+        let opts = CsvReaderOpts {
+            delimiter: b'\t',
+            ..Default::default()
+        };
+        let ret = read_records("fixtures/players.tsv", &opts)?;
+        assert_eq!(ret.len(), 3);
+        assert_eq!(ret[0]["DOB"], "Apr 18, 1990 (29)");
+        assert_eq!(ret[2]["Kit Number"], "77");
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_records_encodings() -> Result<()> {
+        let convert = |input: &[u8], encoding: &str| -> Result<Value> {
+            let opts = CsvReaderOpts {
+                encoding: encoding.parse()?,
+                ..Default::default()
+            };
+            let mut buf = Vec::new();
+            convert_csv(
+                input,
+                &mut buf,
+                OutputFormat::Json,
+                &opts,
+                &CsvSelectOpts::default(),
+                &CsvConvertOpts::default(),
+            )?;
+            Ok(serde_json::from_slice::<Value>(&buf)?[0].clone())
+        };
+        let expected = serde_json::json!({"Name": "José", "City": "Málaga"});
+        let text = "Name,City\nJosé,Málaga\n";
+
+        // the utf-8 BOM must not leak into the first key
+        let bom = [b"\xEF\xBB\xBF", text.as_bytes()].concat();
+        assert_eq!(convert(&bom, "utf-8")?, expected);
+        let utf16: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        assert_eq!(convert(&utf16, "auto")?, expected);
+        assert_eq!(convert(&utf16[2..], "utf-16le")?, expected);
+        let (latin1, _, _) = encoding_rs::WINDOWS_1252.encode(text);
+        assert_eq!(convert(&latin1, "latin1")?, expected);
+        assert_eq!(convert(&latin1, "auto")?, expected);
+        assert!(convert(&latin1, "utf-8").is_err());
+
+        let text = "姓名,城市\n张三,北京\n李四,上海\n王五,广州\n";
+        let (gbk, _, _) = encoding_rs::GBK.encode(text);
+        assert_eq!(
+            convert(&gbk, "gbk")?,
+            serde_json::json!({"姓名": "张三", "城市": "北京"})
+        );
+        assert_eq!(detect_encoding(&gbk), encoding_rs::GBK);
+        assert!("klingon".parse::<InputEncoding>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_records_without_header() -> Result<()> {
+        let opts = CsvReaderOpts {
+            delimiter: b';',
+            header: false,
+            ..Default::default()
+        };
+        let ret = read_records("fixtures/players_noheader.csv", &opts)?;
+        assert_eq!(ret.len(), 3);
+        assert_eq!(ret[0]["col0"], "Wojciech Szczesny");
+        assert_eq!(ret[0]["col4"], "1");
+        assert_eq!(ret[1]["col1"], "Goalkeeper");
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_csv_unions_keys() -> Result<()> {
+        let input = r#"[{"name": "a", "kit": 1}, {"kit": 2, "nation": "Italy", "captain": true}]"#;
+        let records = parse_records(input, OutputFormat::Json)?;
+        let mut buf = Vec::new();
+        let opts = CsvReaderOpts {
+            delimiter: b';',
+            ..Default::default()
+        };
+        write_csv(&records, &mut buf, &opts)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "name;kit;nation;captain\na;1;;\n;2;Italy;true\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_unflatten_round_trip() -> Result<()> {
+        let csv = "id,address.city,tags[0],tags[1]\n1,Turin,gk,captain\n";
+        let convert_opts = CsvConvertOpts {
+            infer_types: true,
+            unflatten: true,
+            ..Default::default()
+        };
+        let opts = CsvReaderOpts::default();
+        let mut buf = Vec::new();
+        convert_csv(
+            csv.as_bytes(),
+            &mut buf,
+            OutputFormat::Ndjson,
+            &opts,
+            &CsvSelectOpts::default(),
+            &convert_opts,
+        )?;
+        let ndjson = String::from_utf8(buf)?;
+        assert_eq!(
+            ndjson,
+            "{\"id\":1,\"address\":{\"city\":\"Turin\"},\"tags\":[\"gk\",\"captain\"]}\n"
+        );
+
+        let records: Vec<Value> = parse_records(&ndjson, OutputFormat::Ndjson)?
+            .into_iter()
+            .map(flatten)
+            .collect();
+        let mut buf = Vec::new();
+        write_csv(&records, &mut buf, &opts)?;
+        assert_eq!(String::from_utf8(buf)?, csv);
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_round_trip() -> Result<()> {
+        let opts = CsvReaderOpts::default();
+        let convert_opts = CsvConvertOpts {
+            infer_types: true,
+            ..Default::default()
+        };
+        for format in [OutputFormat::MsgPack, OutputFormat::Cbor] {
+            let mut buf = Vec::new();
+            convert_csv(
+                File::open("assets/juventus.csv")?,
+                &mut buf,
+                format,
+                &opts,
+                &CsvSelectOpts::default(),
+                &convert_opts,
+            )?;
+            let records = parse_records(&buf, format)?;
+            assert_eq!(records.len(), 27, "{}", format);
+            assert_eq!(records[0]["Kit Number"], 1);
+            assert_eq!(records[0]["Name"], "Wojciech Szczesny");
+
+            let mut csv = Vec::new();
+            write_csv(&records, &mut csv, &opts)?;
+            assert_eq!(csv, fs::read("assets/juventus.csv")?);
+        }
+        // no records is still a valid, empty array
+        let mut buf = Vec::new();
+        record_writer(OutputFormat::Cbor, &mut buf, &convert_opts).finish()?;
+        assert_eq!(buf, [0x80]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_records_from_yaml_and_toml() -> Result<()> {
+        let yaml = "- Name: Danilo\n  Kit Number: 13\n";
+        let records = parse_records(yaml, OutputFormat::Yaml)?;
+        assert_eq!(records[0]["Kit Number"], 13);
+        let toml = "[[records]]\nName = \"Danilo\"\n";
+        let records = parse_records(toml, OutputFormat::Toml)?;
+        assert_eq!(records[0]["Name"], "Danilo");
+        assert!(parse_records("{\"a\": 1}", OutputFormat::Json).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use regex::Regex;
+use std::cmp::Ordering;
+
+use crate::process::csv_select::column_index;
+use crate::process::csv_types::finite_number;
+
+/// A compiled `--where` expression, e.g. `Position == "Defender" && Kit > 10`.
+///
+/// Columns are bare identifiers or `` `quoted with backticks` ``, literals are
+/// numbers or strings in single or double quotes. Supported operators are
+/// `== != < <= > >=`, `contains`, `=~` and `!~` (regex), `&&`, `||`, `!` and
+/// parentheses. Values compare as numbers when both sides are finite numbers.
+#[derive(Debug)]
+pub struct Filter {
+    expr: Expr,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Cmp(Operand, CmpOp, Operand),
+    Regex(Operand, Regex),
+    /// A lone operand is true unless it is empty, `false` or `0`.
+    Truthy(Operand),
+}
+
+#[derive(Debug)]
+enum Operand {
+    Column(usize),
+    Literal(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    /// A `` `quoted` `` column, never a keyword
+    Column(String),
+    Str(String),
+    Num(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+impl Filter {
+    /// Compile `src`, resolving column names against `header`.
+    pub fn parse(src: &str, header: &StringRecord) -> Result<Self> {
+        let tokens = tokenize(src)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            header,
+        };
+        let expr = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(Self { expr }),
+            Some(t) => Err(anyhow!("Unexpected {:?} in filter `{}`", t, src)),
+        }
+    }
+
+    pub fn matches(&self, record: &StringRecord) -> bool {
+        self.expr.eval(record)
+    }
+}
+
+impl Expr {
+    fn eval(&self, record: &StringRecord) -> bool {
+        match self {
+            Expr::Or(a, b) => a.eval(record) || b.eval(record),
+            Expr::And(a, b) => a.eval(record) && b.eval(record),
+            Expr::Not(e) => !e.eval(record),
+            Expr::Cmp(a, op, b) => {
+                let (a, b) = (a.get(record), b.get(record));
+                match op {
+                    CmpOp::Contains => a.contains(b),
+                    CmpOp::Eq => compare(a, b) == Ordering::Equal,
+                    CmpOp::Ne => compare(a, b) != Ordering::Equal,
+                    CmpOp::Lt => compare(a, b) == Ordering::Less,
+                    CmpOp::Le => compare(a, b) != Ordering::Greater,
+                    CmpOp::Gt => compare(a, b) == Ordering::Greater,
+                    CmpOp::Ge => compare(a, b) != Ordering::Less,
+                }
+            }
+            Expr::Regex(a, re) => re.is_match(a.get(record)),
+            Expr::Truthy(a) => !matches!(a.get(record).trim(), "" | "0" | "false" | "FALSE"),
+        }
+    }
+}
+
+impl Operand {
+    fn get<'a>(&'a self, record: &'a StringRecord) -> &'a str {
+        match self {
+            Operand::Column(i) => record.get(*i).unwrap_or_default(),
+            Operand::Literal(s) => s,
+        }
+    }
+}
+
+/// Compare numerically when both sides are finite numbers, as strings otherwise.
+fn compare(a: &str, b: &str) -> Ordering {
+    match (finite_number(a), finite_number(b)) {
+        (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+        _ => a.cmp(b),
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    header: &'a StringRecord,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        let found = match self.peek() {
+            Some(Token::Op(o)) => *o == op,
+            Some(Token::Ident(i)) => i.eq_ignore_ascii_case(op),
+            _ => false,
+        };
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut left = self.and()?;
+        while self.eat("||") || self.eat("or") {
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut left = self.not()?;
+        while self.eat("&&") || self.eat("and") {
+            left = Expr::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.eat("!") || self.eat("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.peek() == Some(&Token::LParen) {
+            self.pos += 1;
+            let expr = self.or()?;
+            return match self.next() {
+                Some(Token::RParen) => Ok(expr),
+                _ => Err(anyhow!("Missing `)` in filter")),
+            };
+        }
+        self.cmp()
+    }
+
+    fn cmp(&mut self) -> Result<Expr> {
+        let left = self.operand()?;
+        let op = match self.peek() {
+            Some(Token::Op(op)) => *op,
+            Some(Token::Ident(i)) if i.eq_ignore_ascii_case("contains") => "contains",
+            _ => return Ok(Expr::Truthy(left)),
+        };
+        let op = match op {
+            "==" => CmpOp::Eq,
+            "!=" => CmpOp::Ne,
+            "<" => CmpOp::Lt,
+            "<=" => CmpOp::Le,
+            ">" => CmpOp::Gt,
+            ">=" => CmpOp::Ge,
+            "contains" => CmpOp::Contains,
+            "=~" | "!~" => {
+                self.pos += 1;
+                let re = match self.next() {
+                    Some(Token::Str(s)) => Regex::new(&s)?,
+                    _ => return Err(anyhow!("`{}` expects a quoted regex", op)),
+                };
+                let expr = Expr::Regex(left, re);
+                return Ok(if op == "!~" {
+                    Expr::Not(Box::new(expr))
+                } else {
+                    expr
+                });
+            }
+            _ => return Ok(Expr::Truthy(left)),
+        };
+        self.pos += 1;
+        Ok(Expr::Cmp(left, op, self.operand()?))
+    }
+
+    fn operand(&mut self) -> Result<Operand> {
+        match self.next() {
+            Some(Token::Ident(name)) | Some(Token::Column(name)) => {
+                Ok(Operand::Column(column_index(self.header, &name)?))
+            }
+            Some(Token::Str(s)) | Some(Token::Num(s)) => Ok(Operand::Literal(s)),
+            Some(t) => Err(anyhow!("Expected a column or value, found {:?}", t)),
+            None => Err(anyhow!("Unexpected end of filter")),
+        }
+    }
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>> {
+    const OPS: [&str; 12] = [
+        "==", "!=", "<=", ">=", "=~", "!~", "&&", "||", "<", ">", "!", "=",
+    ];
+    let mut tokens = Vec::new();
+    let mut chars = src.char_indices().peekable();
+    while let Some(&(i, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' || c == ')' {
+            chars.next();
+            tokens.push(if c == '(' {
+                Token::LParen
+            } else {
+                Token::RParen
+            });
+        } else if c == '"' || c == '\'' || c == '`' {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, e)) => s.push(e),
+                        None => return Err(anyhow!("Unterminated string in filter")),
+                    },
+                    Some((_, e)) if e == c => break,
+                    Some((_, e)) => s.push(e),
+                    None => return Err(anyhow!("Unterminated string in filter")),
+                }
+            }
+            tokens.push(if c == '`' {
+                Token::Column(s)
+            } else {
+                Token::Str(s)
+            });
+        } else if c.is_ascii_digit() || (c == '-' && tokens_expect_value(&tokens)) {
+            let mut s = String::new();
+            while let Some(&(_, d)) = chars.peek() {
+                if d.is_ascii_alphanumeric() || d == '.' || d == '-' || d == '+' {
+                    s.push(d);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Num(s));
+        } else if c.is_alphanumeric() || c == '_' {
+            let mut s = String::new();
+            while let Some(&(_, d)) = chars.peek() {
+                if d.is_alphanumeric() || d == '_' || d == '.' {
+                    s.push(d);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Ident(s));
+        } else {
+            let rest = &src[i..];
+            let op = OPS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .ok_or_else(|| anyhow!("Unexpected `{}` in filter at {}", c, i))?;
+            for _ in 0..op.len() {
+                chars.next();
+            }
+            // a single `=` is accepted as equality
+            tokens.push(Token::Op(if *op == "=" { "==" } else { op }));
+        }
+    }
+    Ok(tokens)
+}
+
+/// A `-` starts a negative number only where a value is expected.
+fn tokens_expect_value(tokens: &[Token]) -> bool {
+    matches!(
+        tokens.last(),
+        None | Some(Token::Op(_)) | Some(Token::LParen)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> StringRecord {
+        StringRecord::from(vec!["Name", "Position", "Kit Number"])
+    }
+
+    fn check(src: &str, record: &[&str]) -> bool {
+        let filter = Filter::parse(src, &header()).unwrap();
+        filter.matches(&StringRecord::from(record.to_vec()))
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let danilo = ["Danilo", "Right-Back", "13"];
+        assert!(check(
+            r#"Position == "Right-Back" && `Kit Number` > 10"#,
+            &danilo
+        ));
+        assert!(!check(
+            r#"Position == "Right-Back" && `Kit Number` > 13"#,
+            &danilo
+        ));
+        // numeric, not lexicographic, comparison
+        assert!(check("`Kit Number` >= 9", &danilo));
+        assert!(check("Position contains 'Back' || Name == 'x'", &danilo));
+        assert!(check(
+            r#"Name =~ "^Dan" and not (Position != "Right-Back")"#,
+            &danilo
+        ));
+        assert!(check(r#"Name !~ "^Ron""#, &danilo));
+        assert!(check("`Kit Number` > -1", &danilo));
+        assert!(check("Name", &danilo));
+        assert!(!check("Name", &["", "x", "1"]));
+        // NaN and infinities aren't numbers, so they compare as text
+        assert!(!check("`Kit Number` == 5", &["x", "y", "NaN"]));
+        assert!(!check("`Kit Number` == 5.0", &["x", "y", "nan"]));
+        assert!(!check("`Kit Number` > 5", &["x", "y", "-inf"]));
+    }
+
+    #[test]
+    fn test_filter_quoted_keywords() -> Result<()> {
+        let header = StringRecord::from(vec!["and", "or", "not"]);
+        let filter = Filter::parse("`and` == 'a' and `not` == `or`", &header)?;
+        assert!(filter.matches(&StringRecord::from(vec!["a", "b", "b"])));
+        assert!(!filter.matches(&StringRecord::from(vec!["a", "b", "c"])));
+        assert!(Filter::parse("`not`", &header)?.matches(&StringRecord::from(vec!["", "", "x"])));
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_errors() {
+        let header = header();
+        assert!(Filter::parse("Age > 3", &header)
+            .unwrap_err()
+            .to_string()
+            .starts_with("Unknown column `Age`"));
+        assert!(Filter::parse("(Name == 'a'", &header).is_err());
+        assert!(Filter::parse("Name == 'a", &header).is_err());
+        assert!(Filter::parse("Name =~ Position", &header).is_err());
+        assert!(Filter::parse("Name == 'a' 'b'", &header).is_err());
+    }
+}
@@ -0,0 +1,249 @@
+use anyhow::{bail, Result};
+use csv::StringRecord;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Read;
+
+use crate::cli::csv::{CsvJoinOpts, JoinKind};
+use crate::process::csv_convert::{csv_headers, csv_reader, write_records};
+use crate::process::csv_select::{column_index, Records};
+use crate::utils::{read_data, write_data};
+
+/// Join `left` with `right` on the key columns in `opts.on`.
+///
+/// The right side is held in memory, keyed by its join columns; the left side streams.
+/// Right key columns are dropped from the output since they equal the left ones.
+pub fn csv_join<'a>(
+    left: impl Read + 'a,
+    right: impl Read,
+    opts: &CsvJoinOpts,
+) -> Result<(StringRecord, Records<'a>)> {
+    let mut left = csv_reader(left, &opts.reader)?;
+    let mut right = csv_reader(right, &opts.reader)?;
+    let left_header = csv_headers(&mut left, &opts.reader)?;
+    let right_header = csv_headers(&mut right, &opts.reader)?;
+
+    let mut left_keys = Vec::with_capacity(opts.on.len());
+    let mut right_keys = Vec::with_capacity(opts.on.len());
+    for on in &opts.on {
+        let (l, r) = on.split_once('=').unwrap_or((on, on));
+        left_keys.push(column_index(&left_header, l)?);
+        right_keys.push(column_index(&right_header, r)?);
+    }
+    let right_rest: Vec<usize> = (0..right_header.len())
+        .filter(|i| !right_keys.contains(i))
+        .collect();
+
+    let mut header = left_header.clone();
+    for &i in &right_rest {
+        let mut name = right_header[i].to_string();
+        while header.iter().any(|h| h == name) {
+            if opts.suffix.is_empty() {
+                bail!(
+                    "Column `{}` is on both sides, pick a non-empty --suffix",
+                    name
+                );
+            }
+            name.push_str(&opts.suffix);
+        }
+        header.push_field(&name);
+    }
+
+    let mut table: HashMap<Vec<String>, Vec<StringRecord>> = HashMap::new();
+    for record in right.into_records() {
+        let record = record?;
+        table
+            .entry(key_of(&record, &right_keys))
+            .or_default()
+            .push(record);
+    }
+
+    let join = Join {
+        left: Box::new(left.into_records().map(|r| r.map_err(Into::into))),
+        how: opts.how,
+        table,
+        matched: HashSet::new(),
+        left_keys,
+        left_width: left_header.len(),
+        right_keys,
+        right_rest,
+        pending: VecDeque::new(),
+        done: false,
+    };
+    Ok((header, Box::new(join)))
+}
+
+pub fn process_csv_join(opts: &CsvJoinOpts) -> Result<()> {
+    let (header, records) = csv_join(read_data(&opts.left)?, read_data(&opts.right)?, opts)?;
+    let writer = write_data(&opts.output)?;
+    write_records(&header, records, writer, opts.format, &opts.convert)
+}
+
+fn key_of(record: &StringRecord, keys: &[usize]) -> Vec<String> {
+    keys.iter()
+        .map(|&i| record.get(i).unwrap_or_default().to_string())
+        .collect()
+}
+
+struct Join<'a> {
+    left: Records<'a>,
+    how: JoinKind,
+    table: HashMap<Vec<String>, Vec<StringRecord>>,
+    matched: HashSet<Vec<String>>,
+    left_keys: Vec<usize>,
+    left_width: usize,
+    right_keys: Vec<usize>,
+    right_rest: Vec<usize>,
+    pending: VecDeque<StringRecord>,
+    done: bool,
+}
+
+impl Join<'_> {
+    fn combine(&self, left: &StringRecord, right: Option<&StringRecord>) -> StringRecord {
+        let mut record = left.clone();
+        for &i in &self.right_rest {
+            record.push_field(right.and_then(|r| r.get(i)).unwrap_or_default());
+        }
+        record
+    }
+
+    /// Right rows no left row matched, with the key copied into the left key columns.
+    fn unmatched_right(&mut self) {
+        let mut keys: Vec<_> = self
+            .table
+            .keys()
+            .filter(|k| !self.matched.contains(*k))
+            .cloned()
+            .collect();
+        // hash map order is random, keep the output deterministic
+        keys.sort();
+        for key in keys {
+            for right in &self.table[&key] {
+                let mut left = vec![""; self.left_width];
+                for (&l, &r) in self.left_keys.iter().zip(&self.right_keys) {
+                    left[l] = right.get(r).unwrap_or_default();
+                }
+                let record = self.combine(&StringRecord::from(left), Some(right));
+                self.pending.push_back(record);
+            }
+        }
+    }
+}
+
+impl Iterator for Join<'_> {
+    type Item = Result<StringRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.pending.pop_front() {
+                return Some(Ok(record));
+            }
+            if self.done {
+                return None;
+            }
+            let left = match self.left.next() {
+                Some(Ok(left)) => left,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.done = true;
+                    if self.how == JoinKind::Full {
+                        self.unmatched_right();
+                    }
+                    continue;
+                }
+            };
+            let key = key_of(&left, &self.left_keys);
+            match self.table.get(&key) {
+                Some(rights) => {
+                    let records: Vec<_> = rights
+                        .iter()
+                        .map(|r| self.combine(&left, Some(r)))
+                        .collect();
+                    self.pending.extend(records);
+                    if self.how == JoinKind::Full {
+                        self.matched.insert(key);
+                    }
+                }
+                None if self.how != JoinKind::Inner => {
+                    let record = self.combine(&left, None);
+                    self.pending.push_back(record);
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::csv::{CsvConvertOpts, CsvReaderOpts, OutputFormat};
+
+    const LEFT: &str = "id,name,team\n1,Danilo,Juventus\n2,Ramsey,Juventus\n3,Perin,Genoa\n";
+    const RIGHT: &str = "id,team,goals\n2,Wales,20\n4,Italy,9\n1,Brazil,1\n1,Brazil U23,0\n";
+
+    fn join(how: JoinKind, on: &str) -> Result<Vec<Vec<String>>> {
+        join_csv(LEFT, RIGHT, how, on, "_right")
+    }
+
+    fn join_csv(
+        left: &str,
+        right: &str,
+        how: JoinKind,
+        on: &str,
+        suffix: &str,
+    ) -> Result<Vec<Vec<String>>> {
+        let opts = CsvJoinOpts {
+            left: "-".into(),
+            right: "-".into(),
+            on: vec![on.into()],
+            how,
+            suffix: suffix.into(),
+            output: "-".into(),
+            format: OutputFormat::Json,
+            reader: CsvReaderOpts::default(),
+            convert: CsvConvertOpts::default(),
+        };
+        let (header, records) = csv_join(left.as_bytes(), right.as_bytes(), &opts)?;
+        let mut rows = vec![header.iter().map(String::from).collect()];
+        for record in records {
+            rows.push(record?.iter().map(String::from).collect());
+        }
+        Ok(rows)
+    }
+
+    #[test]
+    fn test_csv_join_modes() {
+        let rows = join(JoinKind::Inner, "id").unwrap();
+        assert_eq!(rows[0], ["id", "name", "team", "team_right", "goals"]);
+        assert_eq!(rows[1], ["1", "Danilo", "Juventus", "Brazil", "1"]);
+        assert_eq!(rows[2], ["1", "Danilo", "Juventus", "Brazil U23", "0"]);
+        assert_eq!(rows[3], ["2", "Ramsey", "Juventus", "Wales", "20"]);
+        assert_eq!(rows.len(), 4);
+
+        let rows = join(JoinKind::Left, "id").unwrap();
+        assert_eq!(rows.len(), 5);
+        assert_eq!(rows[4], ["3", "Perin", "Genoa", "", ""]);
+
+        let rows = join(JoinKind::Full, "id").unwrap();
+        assert_eq!(rows.len(), 6);
+        assert_eq!(rows[5], ["4", "", "", "Italy", "9"]);
+    }
+
+    #[test]
+    fn test_csv_join_keys() {
+        let rows = join(JoinKind::Inner, "team=team").unwrap();
+        assert_eq!(rows[0], ["id", "name", "team", "id_right", "goals"]);
+        assert_eq!(rows.len(), 1);
+        assert!(join(JoinKind::Inner, "id=player").is_err());
+    }
+
+    #[test]
+    fn test_csv_join_suffix_clash() {
+        let left = "id,team,team_right\n1,Juventus,Genoa\n";
+        let right = "id,team\n1,Italy\n";
+        let rows = join_csv(left, right, JoinKind::Inner, "id", "_right").unwrap();
+        assert_eq!(rows[0], ["id", "team", "team_right", "team_right_right"]);
+        assert_eq!(rows[1], ["1", "Juventus", "Genoa", "Italy"]);
+        assert!(join_csv(left, right, JoinKind::Inner, "id", "").is_err());
+    }
+}
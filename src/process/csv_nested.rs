@@ -0,0 +1,177 @@
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+
+/// Largest array index a column may use, so a stray header can't allocate gigabytes.
+const MAX_INDEX: usize = 9_999;
+
+/// One step of a column path such as `address.city` or `tags[0]`.
+#[derive(Debug, PartialEq, Eq)]
+enum Segment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+/// Split a header into path segments, `None` when it isn't a valid path.
+fn parse_path(header: &str) -> Option<Vec<Segment<'_>>> {
+    let mut segments = Vec::new();
+    for part in header.split('.') {
+        let (key, mut rest) = part.split_at(part.find('[').unwrap_or(part.len()));
+        if key.is_empty() && segments.is_empty() {
+            return None;
+        }
+        if !key.is_empty() {
+            segments.push(Segment::Key(key));
+        } else if rest.is_empty() {
+            // `a..b` or a trailing dot
+            return None;
+        }
+        while !rest.is_empty() {
+            let (index, tail) = rest.strip_prefix('[')?.split_once(']')?;
+            segments.push(Segment::Index(index.parse().ok()?));
+            rest = tail;
+        }
+    }
+    Some(segments)
+}
+
+/// Turn dotted and indexed keys into nested objects and arrays, e.g.
+/// `{"address.city": "Turin", "tags[0]": "gk"}` into
+/// `{"address": {"city": "Turin"}, "tags": ["gk"]}`. Keys that aren't valid paths stay as they are.
+pub fn unflatten(record: Value) -> Result<Value> {
+    let Value::Object(map) = record else {
+        return Ok(record);
+    };
+    let mut root = Value::Object(Map::new());
+    for (key, value) in map {
+        let segments = match parse_path(&key) {
+            Some(segments) => segments,
+            None => vec![Segment::Key(&key)],
+        };
+        for segment in &segments {
+            if let Segment::Index(index) = segment {
+                if *index > MAX_INDEX {
+                    return Err(anyhow!(
+                        "Column `{}` has index {}, the largest allowed is {}",
+                        key,
+                        index,
+                        MAX_INDEX
+                    ));
+                }
+            }
+        }
+        insert(&mut root, &segments, value)
+            .ok_or_else(|| anyhow!("Column `{}` conflicts with another column", key))?;
+    }
+    Ok(root)
+}
+
+fn insert(node: &mut Value, segments: &[Segment], value: Value) -> Option<()> {
+    let Some((first, rest)) = segments.split_first() else {
+        // only an untouched slot may take a value
+        return node.is_null().then(|| *node = value);
+    };
+    let child = match first {
+        Segment::Key(key) => node
+            .as_object_mut()?
+            .entry(key.to_string())
+            .or_insert(Value::Null),
+        Segment::Index(index) => {
+            let items = node.as_array_mut()?;
+            if items.len() <= *index {
+                items.resize(index + 1, Value::Null);
+            }
+            &mut items[*index]
+        }
+    };
+    if child.is_null() && !rest.is_empty() {
+        *child = match rest[0] {
+            Segment::Key(_) => Value::Object(Map::new()),
+            Segment::Index(_) => Value::Array(Vec::new()),
+        };
+    }
+    insert(child, rest, value)
+}
+
+/// The reverse of [`unflatten`]: nested values become dotted and indexed keys.
+pub fn flatten(record: Value) -> Value {
+    let mut map = Map::new();
+    match record {
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                flatten_into(&mut map, key, value);
+            }
+        }
+        v => return v,
+    }
+    Value::Object(map)
+}
+
+fn flatten_into(map: &mut Map<String, Value>, prefix: String, value: Value) {
+    match value {
+        Value::Object(fields) if !fields.is_empty() => {
+            for (key, value) in fields {
+                flatten_into(map, format!("{}.{}", prefix, key), value);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (i, value) in items.into_iter().enumerate() {
+                flatten_into(map, format!("{}[{}]", prefix, i), value);
+            }
+        }
+        v => {
+            map.insert(prefix, v);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_unflatten() {
+        let record = json!({
+            "id": 1,
+            "address.city": "Turin",
+            "address.zip": "10151",
+            "tags[1]": "captain",
+            "tags[0]": "gk",
+            "kits[0].number": 1,
+            "kits[0].home": true,
+            "a..b": "x",
+            "[0]": "y",
+        });
+        let nested = unflatten(record.clone()).unwrap();
+        assert_eq!(
+            nested,
+            json!({
+                "id": 1,
+                "address": {"city": "Turin", "zip": "10151"},
+                "tags": ["gk", "captain"],
+                "kits": [{"number": 1, "home": true}],
+                "a..b": "x",
+                "[0]": "y",
+            })
+        );
+        // map equality ignores key order
+        assert_eq!(flatten(nested), record);
+
+        assert!(unflatten(json!({"a": 1, "a.b": 2})).is_err());
+        assert!(unflatten(json!({"a.b": 1, "a[0]": 2})).is_err());
+        assert_eq!(
+            unflatten(json!({"id": 1, "tags[3]": "gk"})).unwrap(),
+            json!({"id": 1, "tags": [null, null, null, "gk"]})
+        );
+
+        // huge indices are rejected instead of allocating or overflowing
+        let err = unflatten(json!({"t[18446744073709551615]": 1})).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Column `t[18446744073709551615]` has index 18446744073709551615, the largest allowed is 9999"
+        );
+        assert!(unflatten(json!({"t[4000000000000]": 1, "a": 2})).is_err());
+        assert!(unflatten(json!({"t[10000]": 1})).is_err());
+        assert_eq!(unflatten(json!({"t[9999]": 1})).unwrap()["t"][9999], 1);
+    }
+}
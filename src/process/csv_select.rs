@@ -0,0 +1,182 @@
+use anyhow::{anyhow, Result};
+use csv::{Reader, StringRecord};
+use std::io::Read;
+
+use crate::cli::csv::{CsvReaderOpts, CsvSelectOpts};
+use crate::process::csv_convert::csv_headers;
+use crate::process::csv_filter::Filter;
+use crate::process::csv_sort::{sort_records, unique_records, SortKey, SORT_BUFFER_BYTES};
+
+pub type Records<'a> = Box<dyn Iterator<Item = Result<StringRecord>> + 'a>;
+
+/// Read the header, then stream records that pass `--where`, sorted and
+/// deduplicated as asked, projected onto the selected columns. Filter, sort and
+/// unique keys refer to the source column names.
+pub fn select_records<'a, R: Read + 'a>(
+    mut reader: Reader<R>,
+    reader_opts: &CsvReaderOpts,
+    opts: &CsvSelectOpts,
+) -> Result<(StringRecord, Records<'a>)> {
+    let source = csv_headers(&mut reader, reader_opts)?;
+    let filter = match &opts.filter {
+        Some(src) => Some(Filter::parse(src, &source)?),
+        None => None,
+    };
+    let sort_keys = opts
+        .sort_by
+        .iter()
+        .map(|spec| SortKey::parse(spec, &source))
+        .collect::<Result<Vec<_>>>()?;
+    let unique_by = opts
+        .unique_by
+        .iter()
+        .map(|name| column_index(&source, name))
+        .collect::<Result<Vec<_>>>()?;
+    let projection = Projection::new(&source, opts)?;
+    let header = projection.header().clone();
+
+    let mut records: Records<'a> = Box::new(reader.into_records().map(|item| Ok(item?)).filter(
+        move |item: &Result<StringRecord>| match (item, &filter) {
+            (Ok(record), Some(filter)) => filter.matches(record),
+            _ => true,
+        },
+    ));
+    if !sort_keys.is_empty() {
+        records = sort_records(records, sort_keys, SORT_BUFFER_BYTES)?;
+    }
+    if !unique_by.is_empty() {
+        records = unique_records(records, unique_by);
+    }
+    let records = records.map(move |item| item.map(|record| projection.apply(&record)));
+    Ok((header, Box::new(records)))
+}
+
+/// Index of `name` in `header`, with an error listing the available columns.
+pub fn column_index(header: &StringRecord, name: &str) -> Result<usize> {
+    header.iter().position(|h| h == name).ok_or_else(|| {
+        let available: Vec<&str> = header.iter().collect();
+        anyhow!(
+            "Unknown column `{}`, available columns: {}",
+            name,
+            available.join(", ")
+        )
+    })
+}
+
+/// Which columns to keep, in which order, and under which names.
+#[derive(Debug)]
+pub struct Projection {
+    indices: Vec<usize>,
+    header: StringRecord,
+}
+
+impl Projection {
+    pub fn new(header: &StringRecord, opts: &CsvSelectOpts) -> Result<Self> {
+        let mut indices = if opts.select.is_empty() {
+            (0..header.len()).collect()
+        } else {
+            opts.select
+                .iter()
+                .map(|name| column_index(header, name))
+                .collect::<Result<Vec<_>>>()?
+        };
+        for name in &opts.exclude {
+            let i = column_index(header, name)?;
+            indices.retain(|&j| j != i);
+        }
+        let mut names: Vec<String> = indices.iter().map(|&i| header[i].to_string()).collect();
+        for (from, to) in &opts.rename {
+            let i = column_index(header, from)?;
+            if let Some(pos) = indices.iter().position(|&j| j == i) {
+                names[pos] = to.clone();
+            }
+        }
+        let changed =
+            !(opts.select.is_empty() && opts.exclude.is_empty() && opts.rename.is_empty());
+        if changed {
+            for (pos, name) in names.iter().enumerate() {
+                if names[..pos].contains(name) {
+                    return Err(anyhow!(
+                        "Column `{}` appears twice in the output, check --select and --rename",
+                        name
+                    ));
+                }
+            }
+        }
+        Ok(Self {
+            indices,
+            header: StringRecord::from(names),
+        })
+    }
+
+    /// The header after selection and renaming.
+    pub fn header(&self) -> &StringRecord {
+        &self.header
+    }
+
+    pub fn apply(&self, record: &StringRecord) -> StringRecord {
+        self.indices
+            .iter()
+            .map(|&i| record.get(i).unwrap_or_default())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> StringRecord {
+        StringRecord::from(vec!["Name", "Position", "DOB", "Nationality", "Kit Number"])
+    }
+
+    #[test]
+    fn test_projection() -> Result<()> {
+        let opts = CsvSelectOpts {
+            select: vec!["Kit Number".into(), "Name".into(), "DOB".into()],
+            exclude: vec!["DOB".into()],
+            rename: vec![("Kit Number".into(), "kit".into())],
+            ..Default::default()
+        };
+        let projection = Projection::new(&header(), &opts)?;
+        assert_eq!(
+            projection.header(),
+            &StringRecord::from(vec!["kit", "Name"])
+        );
+        let record =
+            StringRecord::from(vec!["Danilo", "Right-Back", "Jul 15, 1991", "Brazil", "13"]);
+        assert_eq!(
+            projection.apply(&record),
+            StringRecord::from(vec!["13", "Danilo"])
+        );
+
+        // a rename onto a kept column would silently drop one of them
+        let opts = CsvSelectOpts {
+            rename: vec![("Name".into(), "Position".into())],
+            ..Default::default()
+        };
+        let err = Projection::new(&header(), &opts).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Column `Position` appears twice"));
+        let opts = CsvSelectOpts {
+            select: vec!["Name".into(), "Name".into()],
+            ..Default::default()
+        };
+        assert!(Projection::new(&header(), &opts).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_projection_unknown_column() {
+        let opts = CsvSelectOpts {
+            exclude: vec!["Age".into()],
+            ..Default::default()
+        };
+        let err = Projection::new(&header(), &opts).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown column `Age`, available columns: Name, Position, DOB, Nationality, Kit Number"
+        );
+    }
+}
@@ -0,0 +1,378 @@
+use anyhow::{anyhow, Result};
+use csv::{ReaderBuilder, StringRecord, StringRecordsIntoIter, WriterBuilder};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+use std::rc::Rc;
+
+use crate::process::csv_select::{column_index, Records};
+use crate::process::csv_types::finite_number;
+
+/// Records held in memory before a sorted run is spilled to a temp file.
+pub const SORT_BUFFER_BYTES: usize = 64 * 1024 * 1024;
+/// Runs merged at once, well below the usual open file limit.
+const MERGE_FAN_IN: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SortKey {
+    index: usize,
+    desc: bool,
+}
+
+impl SortKey {
+    /// Parse `Column` or `Column:asc` / `Column:desc` against `header`.
+    pub fn parse(spec: &str, header: &StringRecord) -> Result<Self> {
+        let (name, desc) = match spec.rsplit_once(':') {
+            Some((name, dir)) if dir.eq_ignore_ascii_case("desc") => (name, true),
+            Some((name, dir)) if dir.eq_ignore_ascii_case("asc") => (name, false),
+            _ => (spec, false),
+        };
+        Ok(Self {
+            index: column_index(header, name)?,
+            desc,
+        })
+    }
+}
+
+/// Compare two records by `keys`, in natural order.
+pub fn compare_records(a: &StringRecord, b: &StringRecord, keys: &[SortKey]) -> Ordering {
+    for key in keys {
+        let a = a.get(key.index).unwrap_or_default();
+        let b = b.get(key.index).unwrap_or_default();
+        let ord = natural_cmp(a, b);
+        let ord = if key.desc { ord.reverse() } else { ord };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Finite numbers compare by value and sort before anything else, other strings
+/// compare digit runs by value so `file2` sorts before `file10`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    match (finite_number(a), finite_number(b)) {
+        (Some(x), Some(y)) => return x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+        (Some(_), None) => return Ordering::Less,
+        (None, Some(_)) => return Ordering::Greater,
+        (None, None) => {}
+    }
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, _) => return Ordering::Less,
+            (_, None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let da = a.len() - a.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                let db = b.len() - b.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                let (na, nb) = (
+                    a[..da].trim_start_matches('0'),
+                    b[..db].trim_start_matches('0'),
+                );
+                let ord = na.len().cmp(&nb.len()).then_with(|| na.cmp(nb));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a = &a[da..];
+                b = &b[db..];
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a = &a[x.len_utf8()..];
+                b = &b[y.len_utf8()..];
+            }
+        }
+    }
+}
+
+/// Sort records by `keys`. Runs larger than `buffer_bytes` are sorted in memory,
+/// spilled to temp files and merged, so input size isn't bounded by memory.
+pub fn sort_records<'a>(
+    records: Records<'a>,
+    keys: Vec<SortKey>,
+    buffer_bytes: usize,
+) -> Result<Records<'a>> {
+    sort_in_runs(records, keys, buffer_bytes, MERGE_FAN_IN)
+}
+
+fn sort_in_runs<'a>(
+    records: Records<'a>,
+    keys: Vec<SortKey>,
+    buffer_bytes: usize,
+    fan_in: usize,
+) -> Result<Records<'a>> {
+    let keys: Rc<[SortKey]> = keys.into();
+    // levels[k] holds runs merged k times, a full level is merged into the next one
+    let mut levels: Vec<Vec<File>> = Vec::new();
+    let mut chunk = Vec::new();
+    let mut size = 0;
+    for item in records {
+        let record = item?;
+        size += record.as_slice().len() + record.len() * 16;
+        chunk.push(record);
+        if size >= buffer_bytes {
+            add_run(&mut levels, spill(&mut chunk, &keys)?, &keys, fan_in)?;
+            size = 0;
+        }
+    }
+    chunk.sort_by(|a, b| compare_records(a, b, &keys));
+    if levels.is_empty() {
+        return Ok(Box::new(chunk.into_iter().map(Ok)));
+    }
+    add_run(&mut levels, spill(&mut chunk, &keys)?, &keys, fan_in)?;
+
+    // higher levels hold earlier input, which must win ties to keep the sort stable
+    let mut runs: Vec<File> = levels.into_iter().rev().flatten().collect();
+    while runs.len() > fan_in {
+        let mut merged = Vec::new();
+        while !runs.is_empty() {
+            let batch = runs.drain(..runs.len().min(fan_in)).collect();
+            merged.push(merge_to_file(batch, &keys)?);
+        }
+        runs = merged;
+    }
+    Ok(Box::new(merge(runs, keys)?))
+}
+
+fn add_run(
+    levels: &mut Vec<Vec<File>>,
+    run: File,
+    keys: &Rc<[SortKey]>,
+    fan_in: usize,
+) -> Result<()> {
+    let mut run = run;
+    let mut level = 0;
+    loop {
+        if levels.len() == level {
+            levels.push(Vec::new());
+        }
+        levels[level].push(run);
+        if levels[level].len() < fan_in {
+            return Ok(());
+        }
+        run = merge_to_file(std::mem::take(&mut levels[level]), keys)?;
+        level += 1;
+    }
+}
+
+fn merge(runs: Vec<File>, keys: Rc<[SortKey]>) -> Result<Merge> {
+    let mut sources: Vec<StringRecordsIntoIter<File>> = runs
+        .into_iter()
+        .map(|file| {
+            ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(file)
+                .into_records()
+        })
+        .collect();
+    let mut heap = BinaryHeap::new();
+    for (source, records) in sources.iter_mut().enumerate() {
+        if let Some(record) = records.next() {
+            heap.push(Run {
+                record: record?,
+                source,
+                keys: keys.clone(),
+            });
+        }
+    }
+    Ok(Merge { sources, heap })
+}
+
+fn merge_to_file(runs: Vec<File>, keys: &Rc<[SortKey]>) -> Result<File> {
+    write_run(merge(runs, keys.clone())?)
+}
+
+fn spill(chunk: &mut Vec<StringRecord>, keys: &[SortKey]) -> Result<File> {
+    chunk.sort_by(|a, b| compare_records(a, b, keys));
+    write_run(chunk.drain(..).map(Ok))
+}
+
+/// Write sorted records to a temp file, rewound for reading.
+fn write_run(records: impl Iterator<Item = Result<StringRecord>>) -> Result<File> {
+    let mut writer = WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_writer(tempfile::tempfile()?);
+    for record in records {
+        writer.write_record(&record?)?;
+    }
+    let mut file = writer.into_inner().map_err(|e| anyhow!("{}", e.error()))?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+/// Head of a sorted run, ordered so the `BinaryHeap` pops the smallest first.
+struct Run {
+    record: StringRecord,
+    source: usize,
+    keys: Rc<[SortKey]>,
+}
+
+impl Ord for Run {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed for a min-heap; earlier runs win ties to keep the sort stable
+        compare_records(&other.record, &self.record, &self.keys)
+            .then_with(|| other.source.cmp(&self.source))
+    }
+}
+
+impl PartialOrd for Run {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Run {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Run {}
+
+struct Merge {
+    sources: Vec<StringRecordsIntoIter<File>>,
+    heap: BinaryHeap<Run>,
+}
+
+impl Iterator for Merge {
+    type Item = Result<StringRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Run {
+            record,
+            source,
+            keys,
+        } = self.heap.pop()?;
+        match self.sources[source].next() {
+            Some(Ok(next)) => self.heap.push(Run {
+                record: next,
+                source,
+                keys,
+            }),
+            Some(Err(e)) => return Some(Err(e.into())),
+            None => {}
+        }
+        Some(Ok(record))
+    }
+}
+
+/// Keep the first record for each distinct value of the `columns`.
+pub fn unique_records(records: Records<'_>, columns: Vec<usize>) -> Records<'_> {
+    let mut seen = HashSet::new();
+    Box::new(records.filter(move |item| match item {
+        Ok(record) => {
+            let key: Vec<String> = columns
+                .iter()
+                .map(|&i| record.get(i).unwrap_or_default().to_string())
+                .collect();
+            seen.insert(key)
+        }
+        Err(_) => true,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(rows: &[[&str; 2]]) -> Records<'static> {
+        let rows: Vec<StringRecord> = rows
+            .iter()
+            .map(|r| StringRecord::from(r.to_vec()))
+            .collect();
+        Box::new(rows.into_iter().map(Ok))
+    }
+
+    fn names(records: Records) -> Vec<String> {
+        records.map(|r| r.unwrap()[0].to_string()).collect()
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        assert_eq!(natural_cmp("9", "10"), Ordering::Less);
+        assert_eq!(natural_cmp("-1.5", "1"), Ordering::Less);
+        assert_eq!(natural_cmp("file2", "file10"), Ordering::Less);
+        assert_eq!(natural_cmp("file02", "file2"), Ordering::Equal);
+        assert_eq!(natural_cmp("b", "a10"), Ordering::Greater);
+        // numbers first, then everything else, whatever it looks like
+        assert_eq!(natural_cmp("-2", "-5x"), Ordering::Less);
+        assert_eq!(natural_cmp("-10", "-5x"), Ordering::Less);
+        assert_eq!(natural_cmp("nan", "5"), Ordering::Greater);
+        assert_eq!(natural_cmp("", "5"), Ordering::Greater);
+
+        // a total order, as sort_by and the merge heap need
+        let values = [
+            "-2", "-10", "-5x", "nan", "NaN", "inf", "5", "5.0", "", "file2", "file10", "file02",
+            "a", "-", "1e400",
+        ];
+        for a in values {
+            assert_eq!(natural_cmp(a, a), Ordering::Equal);
+            for b in values {
+                assert_eq!(natural_cmp(a, b), natural_cmp(b, a).reverse());
+                for c in values {
+                    if natural_cmp(a, b) != Ordering::Greater
+                        && natural_cmp(b, c) != Ordering::Greater
+                    {
+                        assert_ne!(natural_cmp(a, c), Ordering::Greater, "{a} {b} {c}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sort_records_multiple_keys() -> Result<()> {
+        let header = StringRecord::from(vec!["Name", "Kit"]);
+        let keys = vec![
+            SortKey::parse("Kit:desc", &header)?,
+            SortKey::parse("Name", &header)?,
+        ];
+        let rows = [["c", "9"], ["a", "10"], ["b", "9"], ["d", "1"]];
+        for buffer in [SORT_BUFFER_BYTES, 1] {
+            // a one byte buffer spills every record into its own run
+            let sorted = sort_records(records(&rows), keys.clone(), buffer)?;
+            assert_eq!(names(sorted), ["a", "b", "c", "d"]);
+        }
+        assert!(SortKey::parse("Age:desc", &header).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_records_merges_in_passes() -> Result<()> {
+        let header = StringRecord::from(vec!["Name", "Value"]);
+        let keys = vec![SortKey::parse("Value", &header)?];
+        let values = [
+            "-2", "-10", "-5x", "nan", "7", "inf", "5", "", "file2", "file10", "3", "-10",
+        ];
+        let rows: Vec<[&str; 2]> = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| [&"abcdefghijkl"[i..i + 1], *v])
+            .collect();
+        let expected = names(sort_records(
+            records(&rows),
+            keys.clone(),
+            SORT_BUFFER_BYTES,
+        )?);
+        // equal values keep their input order
+        assert_eq!(expected[..3], ["b", "l", "a"]);
+        // one record per run and two runs per merge takes several passes
+        for fan_in in [2, 3, 64] {
+            let sorted = sort_in_runs(records(&rows), keys.clone(), 1, fan_in)?;
+            assert_eq!(names(sorted), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_unique_records() {
+        let rows = [["a", "1"], ["b", "2"], ["c", "1"]];
+        assert_eq!(names(unique_records(records(&rows), vec![1])), ["a", "b"]);
+    }
+}
@@ -0,0 +1,333 @@
+use anyhow::{anyhow, Result};
+use csv::{StringRecord, Writer};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::cli::csv::{CsvCatOpts, CsvReaderOpts, CsvSplitOpts};
+use crate::process::csv_convert::{csv_headers, csv_reader, csv_writer};
+use crate::process::csv_select::column_index;
+use crate::utils::{read_data, write_data};
+
+/// Chunk files kept open at once when splitting by a column. The least recently
+/// used one is closed when another is needed, and reopened for appending later.
+const MAX_OPEN_CHUNKS: usize = 128;
+
+/// An output file and the rows written to it so far.
+struct Chunk {
+    path: String,
+    rows: u64,
+    writer: Option<Writer<Box<dyn Write>>>,
+    last_used: u64,
+}
+
+/// Split the input into files under `opts.out_dir`, each starting with the header.
+/// Returns the paths written, in the order they were created.
+pub fn split_csv(reader: impl Read, opts: &CsvSplitOpts) -> Result<Vec<String>> {
+    split_chunks(reader, opts, MAX_OPEN_CHUNKS)
+}
+
+fn split_chunks(reader: impl Read, opts: &CsvSplitOpts, max_open: usize) -> Result<Vec<String>> {
+    let mut reader = csv_reader(reader, &opts.reader)?;
+    let header = csv_headers(&mut reader, &opts.reader)?;
+    let by = opts
+        .by
+        .as_deref()
+        .map(|by| column_index(&header, by))
+        .transpose()?;
+    let rows = opts.rows.unwrap_or(u64::MAX);
+    if rows == 0 {
+        return Err(anyhow!("--rows must be at least 1"));
+    }
+
+    fs::create_dir_all(&opts.out_dir)?;
+    let (stem, ext) = match opts.input.as_str() {
+        "-" => ("split".to_string(), "csv".to_string()),
+        input => {
+            let path = Path::new(input);
+            let part = |s: Option<&std::ffi::OsStr>, default: &str| {
+                s.map_or(default.into(), |s| s.to_string_lossy().into_owned())
+            };
+            (
+                part(path.file_stem(), "split"),
+                part(path.extension(), "csv"),
+            )
+        }
+    };
+
+    let mut paths = Vec::new();
+    let mut chunks: HashMap<String, Chunk> = HashMap::new();
+    let mut names = HashSet::new();
+    let mut open = 0;
+    let mut current = String::new();
+    let mut record = StringRecord::new();
+    let mut tick = 0;
+    while reader.read_record(&mut record)? {
+        tick += 1;
+        let key = match by {
+            Some(i) => record.get(i).unwrap_or_default().to_string(),
+            None => {
+                let full = chunks.get(&current).is_none_or(|c| c.rows >= rows);
+                if full {
+                    // only one chunk is open at a time when splitting by rows
+                    if let Some(writer) = chunks.get_mut(&current).and_then(|c| c.writer.take()) {
+                        close(writer)?;
+                        open -= 1;
+                    }
+                    current = (paths.len() + 1).to_string();
+                }
+                current.clone()
+            }
+        };
+        let needs_writer = chunks.get(&key).is_none_or(|c| c.writer.is_none());
+        if needs_writer && open >= max_open {
+            let lru = chunks
+                .values_mut()
+                .filter(|c| c.writer.is_some())
+                .min_by_key(|c| c.last_used)
+                .expect("open chunks won't be empty");
+            close(lru.writer.take().expect("chunk is open"))?;
+            open -= 1;
+        }
+        let chunk = match chunks.get_mut(&key) {
+            Some(chunk) => {
+                if chunk.writer.is_none() {
+                    // reopened after being evicted, the header is already there
+                    let file = OpenOptions::new().append(true).open(&chunk.path)?;
+                    let file: Box<dyn Write> = Box::new(BufWriter::new(file));
+                    chunk.writer = Some(csv_writer(file, &opts.reader));
+                    open += 1;
+                }
+                chunk
+            }
+            None => {
+                let mut name = file_part(&key);
+                // distinct values can sanitize to the same name
+                if names.contains(&name) {
+                    name = format!("{}_{}", name, names.len() + 1);
+                }
+                names.insert(name.clone());
+                let path = Path::new(&opts.out_dir).join(format!("{}_{}.{}", stem, name, ext));
+                let path = path.to_string_lossy().into_owned();
+                let mut writer = csv_writer(write_data(&path)?, &opts.reader);
+                if opts.reader.header {
+                    writer.write_record(&header)?;
+                }
+                paths.push(path.clone());
+                open += 1;
+                chunks.entry(key).or_insert(Chunk {
+                    path,
+                    rows: 0,
+                    writer: Some(writer),
+                    last_used: tick,
+                })
+            }
+        };
+        chunk.last_used = tick;
+        chunk
+            .writer
+            .as_mut()
+            .expect("chunk was just opened")
+            .write_record(&record)?;
+        chunk.rows += 1;
+    }
+    for writer in chunks.into_values().filter_map(|c| c.writer) {
+        close(writer)?;
+    }
+    Ok(paths)
+}
+
+fn close(mut writer: Writer<Box<dyn Write>>) -> Result<()> {
+    writer.flush()?;
+    Ok(())
+}
+
+/// Concatenate csv files whose headers hold the same columns, in the order of the first.
+pub fn cat_csv(inputs: &[String], writer: impl Write, opts: &CsvReaderOpts) -> Result<()> {
+    let mut writer = csv_writer(writer, opts);
+    let mut first: Option<(&str, StringRecord)> = None;
+    for input in inputs {
+        let mut reader = csv_reader(read_data(input)?, opts)?;
+        let header = csv_headers(&mut reader, opts)?;
+        let order: Vec<usize> = match &first {
+            None => {
+                if opts.header {
+                    writer.write_record(&header)?;
+                }
+                first = Some((input, header.clone()));
+                (0..header.len()).collect()
+            }
+            Some((name, expected)) => reorder(expected, &header)
+                .ok_or_else(|| incompatible(name, expected, input, &header))?,
+        };
+        let mut record = StringRecord::new();
+        while reader.read_record(&mut record)? {
+            writer.write_record(order.iter().map(|&i| record.get(i).unwrap_or_default()))?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn process_csv_split(opts: &CsvSplitOpts) -> Result<()> {
+    split_csv(read_data(&opts.input)?, opts)?;
+    Ok(())
+}
+
+pub fn process_csv_cat(opts: &CsvCatOpts) -> Result<()> {
+    cat_csv(&opts.inputs, write_data(&opts.output)?, &opts.reader)
+}
+
+/// Where each column of `expected` sits in `header`, if both hold the same columns.
+fn reorder(expected: &StringRecord, header: &StringRecord) -> Option<Vec<usize>> {
+    if expected.len() != header.len() {
+        return None;
+    }
+    expected
+        .iter()
+        .map(|name| header.iter().position(|h| h == name))
+        .collect()
+}
+
+fn incompatible(
+    name: &str,
+    expected: &StringRecord,
+    input: &str,
+    header: &StringRecord,
+) -> anyhow::Error {
+    let missing: Vec<&str> = expected
+        .iter()
+        .filter(|c| !header.iter().any(|h| h == *c))
+        .collect();
+    let extra: Vec<&str> = header
+        .iter()
+        .filter(|h| !expected.iter().any(|c| c == *h))
+        .collect();
+    anyhow!(
+        "Columns of {} don't match {}: missing [{}], extra [{}]",
+        input,
+        name,
+        missing.join(", "),
+        extra.join(", ")
+    )
+}
+
+/// Make a column value safe to use in a file name.
+fn file_part(value: &str) -> String {
+    let part: String = value
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if part.is_empty() {
+        "empty".into()
+    } else {
+        part
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    fn split(dir: &Path, rows: Option<u64>, by: Option<&str>) -> Result<Vec<String>> {
+        split_with(dir, rows, by, MAX_OPEN_CHUNKS)
+    }
+
+    fn split_with(
+        dir: &Path,
+        rows: Option<u64>,
+        by: Option<&str>,
+        max_open: usize,
+    ) -> Result<Vec<String>> {
+        let opts = CsvSplitOpts {
+            input: "assets/juventus.csv".into(),
+            out_dir: dir.to_string_lossy().into_owned(),
+            rows,
+            by: by.map(String::from),
+            reader: CsvReaderOpts::default(),
+        };
+        split_chunks(File::open(&opts.input)?, &opts, max_open)
+    }
+
+    #[test]
+    fn test_split_csv() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let paths = split(dir.path(), Some(10), None)?;
+        // 27 players
+        assert_eq!(paths.len(), 3);
+        assert!(paths[0].ends_with("juventus_1.csv"));
+        let last = fs::read_to_string(&paths[2])?;
+        assert!(last.starts_with("Name,Position,DOB,Nationality,Kit Number\n"));
+        assert_eq!(last.lines().count(), 8);
+
+        let paths = split(dir.path(), None, Some("Position"))?;
+        assert!(paths[0].ends_with("juventus_Goalkeeper.csv"));
+        assert!(paths
+            .iter()
+            .any(|p| p.ends_with("juventus_Centre-Back.csv")));
+        assert!(paths
+            .iter()
+            .any(|p| p.ends_with("juventus_Central_Midfield.csv")));
+        assert_eq!(fs::read_to_string(&paths[0])?.lines().count(), 5);
+
+        // splitting and concatenating again gives the original rows back
+        let paths = split(dir.path(), Some(4), None)?;
+        let mut buf = Vec::new();
+        cat_csv(&paths, &mut buf, &CsvReaderOpts::default())?;
+        assert_eq!(buf, fs::read("assets/juventus.csv")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_csv_reopens_evicted_chunks() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let expected: Vec<(String, String)> = split(dir.path(), None, Some("Position"))?
+            .into_iter()
+            .map(|p| Ok((p.clone(), fs::read_to_string(p)?)))
+            .collect::<Result<_>>()?;
+        assert!(expected.len() > 2);
+
+        // far more positions than open files, every chunk gets closed and reopened
+        let dir = tempfile::tempdir()?;
+        let paths = split_with(dir.path(), None, Some("Position"), 2)?;
+        assert_eq!(paths.len(), expected.len());
+        for (path, (_, contents)) in paths.iter().zip(&expected) {
+            let actual = fs::read_to_string(path)?;
+            assert_eq!(&actual, contents);
+            assert_eq!(actual.matches("Name,Position").count(), 1);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_cat_csv_reorders_columns() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = |name: &str, contents: &str| -> Result<String> {
+            let path = dir.path().join(name);
+            fs::write(&path, contents)?;
+            Ok(path.to_string_lossy().into_owned())
+        };
+        let a = file("a.tsv", "id\tname\n1\tDanilo\n")?;
+        let b = file("b.tsv", "name\tid\nRamsey\t8\n")?;
+        let c = file("c.tsv", "name\tkit\nPerin\t37\n")?;
+        let opts = CsvReaderOpts {
+            delimiter: b'\t',
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        cat_csv(&[a.clone(), b], &mut buf, &opts)?;
+        assert_eq!(String::from_utf8(buf)?, "id\tname\n1\tDanilo\n8\tRamsey\n");
+
+        let err = cat_csv(&[a, c], Vec::new(), &opts).unwrap_err();
+        assert!(err.to_string().contains("missing [id], extra [kit]"));
+        Ok(())
+    }
+}
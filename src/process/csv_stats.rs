@@ -0,0 +1,378 @@
+use anyhow::{anyhow, Result};
+use hyperloglogplus::{HyperLogLog, HyperLogLogPlus};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+use std::io::Read;
+
+use crate::cli::csv::{CsvConvertOpts, CsvReaderOpts, CsvSelectOpts, CsvStatsOpts};
+use crate::process::csv_convert::{csv_reader, record_writer};
+use crate::process::csv_select::select_records;
+use crate::process::csv_sort::natural_cmp;
+use crate::process::csv_types::ColumnType;
+use crate::process::table::{render_table, TableStyle};
+use crate::utils::{read_data, write_data};
+
+/// Profile of a single csv column.
+#[derive(Debug, Serialize)]
+pub struct ColumnStats {
+    pub column: String,
+    #[serde(rename = "type")]
+    pub kind: ColumnType,
+    pub count: u64,
+    pub nulls: u64,
+    pub distinct: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stddev: Option<f64>,
+    pub top: Vec<TopValue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopValue {
+    pub value: String,
+    pub count: u64,
+}
+
+/// Deterministic hashing so approximate counts are stable between runs.
+type Hll = HyperLogLogPlus<str, BuildHasherDefault<DefaultHasher>>;
+
+/// Value frequencies: exact, or a HyperLogLog sketch plus a bounded set of heavy hitters.
+enum Counter {
+    Exact(HashMap<String, u64>),
+    Approx {
+        hll: Box<Hll>,
+        heavy: HashMap<String, u64>,
+        capacity: usize,
+    },
+}
+
+impl Counter {
+    fn new(approx: bool, top: usize) -> Result<Self> {
+        if !approx {
+            return Ok(Counter::Exact(HashMap::new()));
+        }
+        let hll = Hll::new(14, Default::default()).map_err(|e| anyhow!("{:?}", e))?;
+        Ok(Counter::Approx {
+            hll: Box::new(hll),
+            heavy: HashMap::new(),
+            capacity: (top * 100).max(1000),
+        })
+    }
+
+    fn insert(&mut self, value: &str) {
+        match self {
+            Counter::Exact(counts) => *counts.entry(value.to_string()).or_default() += 1,
+            Counter::Approx {
+                hll,
+                heavy,
+                capacity,
+            } => {
+                hll.insert(value);
+                *heavy.entry(value.to_string()).or_default() += 1;
+                if heavy.len() > *capacity {
+                    // drop the rarer half, frequent values keep their lead
+                    let keep = *capacity / 2;
+                    let mut entries: Vec<_> = heavy.drain().collect();
+                    sort_by_count(&mut entries);
+                    entries.truncate(keep);
+                    heavy.extend(entries);
+                }
+            }
+        }
+    }
+
+    fn distinct(&mut self) -> u64 {
+        match self {
+            Counter::Exact(counts) => counts.len() as u64,
+            Counter::Approx { hll, .. } => hll.count().round() as u64,
+        }
+    }
+
+    fn top(self, n: usize) -> Vec<TopValue> {
+        let counts = match self {
+            Counter::Exact(counts) => counts,
+            Counter::Approx { heavy, .. } => heavy,
+        };
+        let mut entries: Vec<_> = counts.into_iter().collect();
+        sort_by_count(&mut entries);
+        entries
+            .into_iter()
+            .take(n)
+            .map(|(value, count)| TopValue { value, count })
+            .collect()
+    }
+}
+
+fn sort_by_count(entries: &mut [(String, u64)]) {
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| natural_cmp(&a.0, &b.0)));
+}
+
+struct Profile {
+    kind: ColumnType,
+    count: u64,
+    nulls: u64,
+    counter: Counter,
+    min: Option<String>,
+    max: Option<String>,
+    /// Welford's running mean and sum of squared deviations of numeric values
+    mean: f64,
+    m2: f64,
+    num_min: f64,
+    num_max: f64,
+    /// integers kept apart, f64 loses precision past 2^53
+    int_min: Option<i64>,
+    int_max: Option<i64>,
+}
+
+impl Profile {
+    fn new(approx: bool, top: usize) -> Result<Self> {
+        Ok(Self {
+            kind: ColumnType::Null,
+            count: 0,
+            nulls: 0,
+            counter: Counter::new(approx, top)?,
+            min: None,
+            max: None,
+            mean: 0.0,
+            m2: 0.0,
+            num_min: f64::INFINITY,
+            num_max: f64::NEG_INFINITY,
+            int_min: None,
+            int_max: None,
+        })
+    }
+
+    fn add(&mut self, field: &str) {
+        let kind = ColumnType::of(field);
+        self.kind = self.kind.merge(kind);
+        if kind == ColumnType::Null {
+            self.nulls += 1;
+            return;
+        }
+        self.count += 1;
+        self.counter.insert(field);
+        if self
+            .min
+            .as_deref()
+            .is_none_or(|m| natural_cmp(field, m).is_lt())
+        {
+            self.min = Some(field.to_string());
+        }
+        if self
+            .max
+            .as_deref()
+            .is_none_or(|m| natural_cmp(field, m).is_gt())
+        {
+            self.max = Some(field.to_string());
+        }
+        if let (ColumnType::Integer | ColumnType::Float, Ok(x)) =
+            (kind, field.trim().parse::<f64>())
+        {
+            let n = self.count as f64;
+            let delta = x - self.mean;
+            self.mean += delta / n;
+            self.m2 += delta * (x - self.mean);
+            self.num_min = self.num_min.min(x);
+            self.num_max = self.num_max.max(x);
+        }
+        if let (ColumnType::Integer, Ok(i)) = (kind, field.trim().parse::<i64>()) {
+            self.int_min = Some(self.int_min.map_or(i, |m| m.min(i)));
+            self.int_max = Some(self.int_max.map_or(i, |m| m.max(i)));
+        }
+    }
+
+    fn finish(mut self, column: String, top: usize) -> ColumnStats {
+        let numeric = matches!(self.kind, ColumnType::Integer | ColumnType::Float);
+        let (min, max) = match self.kind {
+            ColumnType::Integer => (self.int_min.map(Value::from), self.int_max.map(Value::from)),
+            ColumnType::Float => (
+                Some(Value::from(self.num_min)),
+                Some(Value::from(self.num_max)),
+            ),
+            _ => (
+                self.min.take().map(Value::from),
+                self.max.take().map(Value::from),
+            ),
+        };
+        let stddev = if self.count > 1 {
+            (self.m2 / (self.count - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+        ColumnStats {
+            column,
+            kind: self.kind,
+            count: self.count,
+            nulls: self.nulls,
+            distinct: self.counter.distinct(),
+            min,
+            max,
+            mean: numeric.then_some(self.mean),
+            stddev: numeric.then_some(stddev),
+            top: self.counter.top(top),
+        }
+    }
+}
+
+/// Profile every selected column of the csv in one pass.
+pub fn csv_stats(
+    reader: impl Read,
+    reader_opts: &CsvReaderOpts,
+    select_opts: &CsvSelectOpts,
+    top: usize,
+    approx: bool,
+) -> Result<Vec<ColumnStats>> {
+    let reader = csv_reader(reader, reader_opts)?;
+    let (header, records) = select_records(reader, reader_opts, select_opts)?;
+    let mut profiles = (0..header.len())
+        .map(|_| Profile::new(approx, top))
+        .collect::<Result<Vec<_>>>()?;
+    for item in records {
+        let record = item?;
+        for (profile, field) in profiles.iter_mut().zip(record.iter()) {
+            profile.add(field);
+        }
+    }
+    Ok(profiles
+        .into_iter()
+        .zip(header.iter())
+        .map(|(profile, column)| profile.finish(column.to_string(), top))
+        .collect())
+}
+
+pub fn process_csv_stats(opts: &CsvStatsOpts, style: TableStyle) -> Result<()> {
+    let stats = csv_stats(
+        read_data(&opts.input)?,
+        &opts.reader,
+        &opts.select,
+        opts.top,
+        opts.approx,
+    )?;
+    let mut output = write_data(&opts.output)?;
+    match opts.format {
+        Some(format) => {
+            let convert_opts = CsvConvertOpts {
+                toml_key: "columns".into(),
+                ..Default::default()
+            };
+            let mut writer = record_writer(format, output, &convert_opts);
+            for column in stats {
+                writer.write_record(serde_json::to_value(column)?)?;
+            }
+            writer.finish()
+        }
+        None => {
+            let header: Vec<String> = [
+                "column", "type", "count", "nulls", "distinct", "min", "max", "mean", "stddev",
+                "top",
+            ]
+            .map(String::from)
+            .to_vec();
+            let rows: Vec<Vec<String>> = stats.iter().map(stats_row).collect();
+            output.write_all(render_table(&header, &rows, style, 32).as_bytes())?;
+            output.flush()?;
+            Ok(())
+        }
+    }
+}
+
+fn stats_row(s: &ColumnStats) -> Vec<String> {
+    let text = |v: &Option<Value>| match v {
+        Some(Value::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+        None => String::new(),
+    };
+    let float = |v: Option<f64>| v.map(|v| format!("{:.2}", v)).unwrap_or_default();
+    let top: Vec<String> = s
+        .top
+        .iter()
+        .map(|t| format!("{} ({})", t.value, t.count))
+        .collect();
+    vec![
+        s.column.clone(),
+        s.kind.to_string(),
+        s.count.to_string(),
+        s.nulls.to_string(),
+        s.distinct.to_string(),
+        text(&s.min),
+        text(&s.max),
+        float(s.mean),
+        float(s.stddev),
+        top.join(", "),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_csv_stats() -> Result<()> {
+        let input = File::open("assets/juventus.csv")?;
+        let stats = csv_stats(input, &Default::default(), &Default::default(), 2, false)?;
+        assert_eq!(stats.len(), 5);
+
+        let kit = &stats[4];
+        assert_eq!(kit.column, "Kit Number");
+        assert_eq!(kit.kind, ColumnType::Integer);
+        assert_eq!((kit.count, kit.nulls, kit.distinct), (27, 0, 27));
+        assert_eq!(kit.min, Some(Value::from(1)));
+        assert_eq!(kit.max, Some(Value::from(77)));
+        assert!(kit.mean.is_some() && kit.stddev.is_some());
+
+        let nationality = &stats[3];
+        assert_eq!(nationality.kind, ColumnType::String);
+        assert_eq!(nationality.mean, None);
+        assert_eq!(nationality.top[0].value, "Italy");
+        assert_eq!(nationality.top[0].count, 8);
+        assert_eq!(nationality.top[1].value, "Brazil");
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_stats_approx() -> Result<()> {
+        let mut input = String::from("id,group,score\n");
+        for i in 0..5000 {
+            input.push_str(&format!("{},g{},\n", i, i % 7));
+        }
+        let stats = csv_stats(
+            input.as_bytes(),
+            &Default::default(),
+            &Default::default(),
+            3,
+            true,
+        )?;
+        let id = stats[0].distinct as f64;
+        assert!((id - 5000.0).abs() / 5000.0 < 0.02, "estimated {}", id);
+        assert_eq!(stats[1].distinct, 7);
+        assert_eq!(stats[1].top.len(), 3);
+        assert_eq!(stats[2].kind, ColumnType::Null);
+        assert_eq!(stats[2].nulls, 5000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_stats_large_integers() -> Result<()> {
+        let input = "n\n9007199254740993\n-9007199254740993\n9007199254740992\n";
+        let stats = csv_stats(
+            input.as_bytes(),
+            &Default::default(),
+            &Default::default(),
+            1,
+            false,
+        )?;
+        assert_eq!(stats[0].kind, ColumnType::Integer);
+        assert_eq!(stats[0].min, Some(Value::from(-9007199254740993i64)));
+        assert_eq!(stats[0].max, Some(Value::from(9007199254740993i64)));
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+use csv::StringRecord;
+use serde::Serialize;
+use serde_json::{Number, Value};
+use std::fmt;
+
+/// How many records are sampled to decide the type of each column.
+pub const INFER_SAMPLE: usize = 1000;
+
+/// Inferred type of a csv column, ordered from the most to the least specific.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    /// Only empty values seen so far
+    Null,
+    Bool,
+    Integer,
+    Float,
+    /// ISO 8601 date or datetime, kept as a string in the output
+    Date,
+    String,
+}
+
+impl ColumnType {
+    /// Type of a single field.
+    pub fn of(field: &str) -> Self {
+        let field = field.trim();
+        if field.is_empty() {
+            ColumnType::Null
+        } else if field.eq_ignore_ascii_case("true") || field.eq_ignore_ascii_case("false") {
+            ColumnType::Bool
+        } else if is_integer(field) {
+            ColumnType::Integer
+        } else if is_float(field) {
+            ColumnType::Float
+        } else if is_iso_date(field) {
+            ColumnType::Date
+        } else {
+            ColumnType::String
+        }
+    }
+
+    /// Narrowest type that can hold values of both `self` and `other`.
+    pub fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (ColumnType::Null, t) | (t, ColumnType::Null) => t,
+            (ColumnType::Integer, ColumnType::Float) | (ColumnType::Float, ColumnType::Integer) => {
+                ColumnType::Float
+            }
+            _ => ColumnType::String,
+        }
+    }
+
+    /// Convert a field to a typed value, falling back to a string when it doesn't fit.
+    pub fn parse(self, field: &str) -> Value {
+        let trimmed = field.trim();
+        if trimmed.is_empty() && self != ColumnType::String {
+            return Value::Null;
+        }
+        let typed = match self {
+            ColumnType::Bool => match trimmed.to_ascii_lowercase().as_str() {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            ColumnType::Integer if is_integer(trimmed) => {
+                trimmed.parse::<i64>().ok().map(Value::from)
+            }
+            ColumnType::Float if is_integer(trimmed) || is_float(trimmed) => trimmed
+                .parse::<f64>()
+                .ok()
+                .and_then(Number::from_f64)
+                .map(Value::Number),
+            _ => None,
+        };
+        typed.unwrap_or_else(|| Value::String(field.to_string()))
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ColumnType::Null => "null",
+            ColumnType::Bool => "bool",
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Date => "date",
+            ColumnType::String => "string",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Infer the type of each of the `width` columns from a sample of records.
+pub fn infer_types(records: &[StringRecord], width: usize) -> Vec<ColumnType> {
+    let mut types = vec![ColumnType::Null; width];
+    for record in records {
+        for (t, field) in types.iter_mut().zip(record.iter()) {
+            *t = t.merge(ColumnType::of(field));
+        }
+    }
+    types
+}
+
+/// `s` as a number, unless it's not one or is `NaN` or infinite.
+pub fn finite_number(s: &str) -> Option<f64> {
+    s.trim().parse::<f64>().ok().filter(|x| x.is_finite())
+}
+
+fn is_integer(s: &str) -> bool {
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    // leading zeros usually mean an identifier such as a zip code, not a number
+    !digits.is_empty()
+        && digits.bytes().all(|b| b.is_ascii_digit())
+        && (digits.len() == 1 || !digits.starts_with('0'))
+        && s.parse::<i64>().is_ok()
+}
+
+fn is_float(s: &str) -> bool {
+    let unsigned = s.strip_prefix(['-', '+']).unwrap_or(s);
+    let int_part = unsigned.split(['.', 'e', 'E']).next().unwrap_or_default();
+    // only plain decimal notation, so `inf`, `nan` and friends stay strings, and a
+    // fraction or exponent, so integers too large for i64 aren't rounded into floats
+    (int_part.len() <= 1 || !int_part.starts_with('0'))
+        && s.bytes().any(|b| matches!(b, b'.' | b'e' | b'E'))
+        && s.bytes()
+            .all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'-' | b'+' | b'e' | b'E'))
+        && s.bytes().any(|b| b.is_ascii_digit())
+        && s.parse::<f64>().is_ok()
+}
+
+fn is_iso_date(s: &str) -> bool {
+    let b = s.as_bytes();
+    if b.len() < 10 || b[4] != b'-' || b[7] != b'-' {
+        return false;
+    }
+    let digits = |r: std::ops::Range<usize>| b[r].iter().all(u8::is_ascii_digit);
+    if !(digits(0..4) && digits(5..7) && digits(8..10)) {
+        return false;
+    }
+    let month: u8 = s[5..7].parse().unwrap_or(0);
+    let day: u8 = s[8..10].parse().unwrap_or(0);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return false;
+    }
+    match b.get(10) {
+        None => true,
+        Some(b'T') | Some(b' ') => {
+            b.len() >= 16 && digits(11..13) && b[13] == b':' && digits(14..16)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_type_of() {
+        assert_eq!(ColumnType::of(""), ColumnType::Null);
+        assert_eq!(ColumnType::of("TRUE"), ColumnType::Bool);
+        assert_eq!(ColumnType::of("-42"), ColumnType::Integer);
+        assert_eq!(ColumnType::of("007"), ColumnType::String);
+        assert_eq!(ColumnType::of("3.14"), ColumnType::Float);
+        assert_eq!(ColumnType::of("1e3"), ColumnType::Float);
+        assert_eq!(ColumnType::of("nan"), ColumnType::String);
+        assert_eq!(ColumnType::of("12345678901234567890"), ColumnType::String);
+        assert_eq!(ColumnType::of("-9223372036854775809"), ColumnType::String);
+        assert_eq!(ColumnType::of("2019-08-12"), ColumnType::Date);
+        assert_eq!(ColumnType::of("2019-08-12T10:30:00Z"), ColumnType::Date);
+        assert_eq!(ColumnType::of("2019-13-12"), ColumnType::String);
+        assert_eq!(ColumnType::of("Apr 18, 1990 (29)"), ColumnType::String);
+    }
+
+    #[test]
+    fn test_infer_and_parse() {
+        let records = vec![
+            StringRecord::from(vec!["1", "1.5", "true", "", "x"]),
+            StringRecord::from(vec!["2", "2", "false", "", "3"]),
+            StringRecord::from(vec!["", "", "", "", ""]),
+        ];
+        let types = infer_types(&records, 5);
+        assert_eq!(
+            types,
+            vec![
+                ColumnType::Integer,
+                ColumnType::Float,
+                ColumnType::Bool,
+                ColumnType::Null,
+                ColumnType::String
+            ]
+        );
+        assert_eq!(types[0].parse("2"), Value::from(2));
+        assert_eq!(types[0].parse(""), Value::Null);
+        assert_eq!(types[1].parse("2"), Value::from(2.0));
+        assert_eq!(types[2].parse("False"), Value::Bool(false));
+        // values outside the sample that don't fit stay strings
+        assert_eq!(types[0].parse("n/a"), Value::from("n/a"));
+        assert_eq!(types[4].parse(""), Value::from(""));
+        // ids beyond i64 are kept verbatim, not rounded
+        assert_eq!(
+            types[1].parse("12345678901234567890"),
+            Value::from("12345678901234567890")
+        );
+    }
+}
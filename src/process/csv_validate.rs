@@ -0,0 +1,393 @@
+use anyhow::{anyhow, Context, Result};
+use csv::StringRecord;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use crate::cli::csv::CsvReaderOpts;
+use crate::process::csv_convert::{csv_headers, csv_reader};
+use crate::process::csv_types::ColumnType;
+use crate::utils::{read_data, write_data};
+
+/// Type a column's values must parse as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueType {
+    String,
+    Integer,
+    Number,
+    Boolean,
+    Date,
+}
+
+/// Checks applied to every value of one column.
+#[derive(Debug)]
+pub struct ColumnRule {
+    pub name: String,
+    pub kind: Option<ValueType>,
+    pub required: bool,
+    pub pattern: Option<Regex>,
+    pub allowed: Option<Vec<String>>,
+}
+
+/// Column rules read from a JSON Schema or a toml column spec.
+#[derive(Debug)]
+pub struct Schema {
+    pub columns: Vec<ColumnRule>,
+}
+
+/// A single failed check, `row` being the line number in the input.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Violation {
+    pub row: u64,
+    pub column: Option<usize>,
+    pub name: String,
+    pub message: String,
+}
+
+/// One column of the toml spec, e.g. `[columns."Kit Number"]`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ColumnSpec {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
+    required: bool,
+    regex: Option<String>,
+    #[serde(rename = "enum")]
+    allowed: Option<Vec<Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlSchema {
+    columns: BTreeMap<String, ColumnSpec>,
+}
+
+impl Schema {
+    /// Load a schema, `.toml` files are column specs, anything else is JSON Schema.
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = fs::read_to_string(path).with_context(|| format!("Can't read {}", path))?;
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml(&contents),
+            _ => Self::from_json_schema(&contents),
+        }
+    }
+
+    /// Parse a toml column spec, one `[columns.<name>]` table per column.
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let spec: TomlSchema = toml::from_str(contents)?;
+        let columns = spec
+            .columns
+            .into_iter()
+            .map(|(name, spec)| {
+                Ok(ColumnRule {
+                    kind: spec.kind.as_deref().map(str::parse).transpose()?,
+                    required: spec.required,
+                    pattern: compile(&name, spec.regex.as_deref())?,
+                    allowed: spec.allowed.map(|v| v.iter().map(value_text).collect()),
+                    name,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { columns })
+    }
+
+    /// Parse the subset of JSON Schema that maps onto csv columns: `properties`
+    /// with `type`, `format`, `pattern` and `enum`, plus the `required` list.
+    pub fn from_json_schema(contents: &str) -> Result<Self> {
+        let schema: Value = serde_json::from_str(contents)?;
+        let empty = Map::new();
+        let properties = match schema.get("properties") {
+            Some(Value::Object(properties)) => properties,
+            Some(_) => return Err(anyhow!("`properties` must be an object")),
+            None => &empty,
+        };
+        let required: Vec<&str> = match schema.get("required") {
+            Some(Value::Array(names)) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        let mut columns = properties
+            .iter()
+            .map(|(name, property)| {
+                let types: Vec<&str> = match property.get("type") {
+                    Some(Value::String(t)) => vec![t.as_str()],
+                    Some(Value::Array(ts)) => ts.iter().filter_map(Value::as_str).collect(),
+                    _ => Vec::new(),
+                };
+                // `["integer", "null"]` makes an otherwise required column nullable
+                let kind = match types.iter().find(|t| **t != "null") {
+                    Some(_)
+                        if matches!(
+                            property.get("format").and_then(Value::as_str),
+                            Some("date" | "date-time")
+                        ) =>
+                    {
+                        Some(ValueType::Date)
+                    }
+                    Some(t) => Some(t.parse()?),
+                    None => None,
+                };
+                Ok(ColumnRule {
+                    name: name.clone(),
+                    kind,
+                    required: required.contains(&name.as_str()) && !types.contains(&"null"),
+                    pattern: compile(name, property.get("pattern").and_then(Value::as_str))?,
+                    allowed: property
+                        .get("enum")
+                        .and_then(Value::as_array)
+                        .map(|v| v.iter().map(value_text).collect()),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // required names without a property still have to be present
+        for name in required {
+            if !properties.contains_key(name) {
+                columns.push(ColumnRule {
+                    name: name.to_string(),
+                    kind: None,
+                    required: true,
+                    pattern: None,
+                    allowed: None,
+                });
+            }
+        }
+        Ok(Self { columns })
+    }
+}
+
+impl ColumnRule {
+    /// Why `value` breaks this rule, if it does. Empty values only fail `required`.
+    pub fn check(&self, value: &str) -> Option<String> {
+        if value.trim().is_empty() {
+            return self.required.then(|| "required value is missing".into());
+        }
+        if let Some(kind) = self.kind {
+            if !kind.matches(value) {
+                return Some(format!("expected {}, got `{}`", kind, value));
+            }
+        }
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(value) {
+                return Some(format!("`{}` does not match /{}/", value, pattern));
+            }
+        }
+        match &self.allowed {
+            Some(allowed) if !allowed.iter().any(|a| a == value) => {
+                Some(format!("`{}` is not one of {}", value, allowed.join(", ")))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl ValueType {
+    fn matches(self, value: &str) -> bool {
+        let value = value.trim();
+        match self {
+            ValueType::String => true,
+            ValueType::Integer => value.parse::<i64>().is_ok(),
+            ValueType::Number => value.parse::<f64>().is_ok_and(f64::is_finite),
+            ValueType::Boolean => {
+                value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false")
+            }
+            ValueType::Date => ColumnType::of(value) == ColumnType::Date,
+        }
+    }
+}
+
+/// Check every record against `schema`, writing one line per violation.
+/// Returns the number of violations found.
+pub fn validate_csv(
+    reader: impl Read,
+    schema: &Schema,
+    opts: &CsvReaderOpts,
+    mut writer: impl Write,
+) -> Result<u64> {
+    let mut reader = csv_reader(reader, opts)?;
+    let header = csv_headers(&mut reader, opts)?;
+    let mut found = 0;
+    let mut report = |violation: Violation| -> Result<()> {
+        found += 1;
+        writeln!(writer, "{}", violation)?;
+        Ok(())
+    };
+
+    let mut rules = Vec::new();
+    for rule in &schema.columns {
+        match header.iter().position(|h| h == rule.name) {
+            Some(index) => rules.push((index, rule)),
+            None if rule.required => report(Violation {
+                row: 1,
+                column: None,
+                name: rule.name.clone(),
+                message: "required column is missing".into(),
+            })?,
+            None => {}
+        }
+    }
+    rules.sort_by_key(|(index, _)| *index);
+
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        let row = record.position().map_or(0, |p| p.line());
+        for (index, rule) in &rules {
+            if let Some(message) = rule.check(record.get(*index).unwrap_or_default()) {
+                report(Violation {
+                    row,
+                    column: Some(index + 1),
+                    name: rule.name.clone(),
+                    message,
+                })?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(found)
+}
+
+pub fn process_csv_validate(
+    input: &str,
+    output: &str,
+    schema: &str,
+    opts: &CsvReaderOpts,
+) -> Result<()> {
+    let schema = Schema::load(schema)?;
+    let found = validate_csv(read_data(input)?, &schema, opts, write_data(output)?)?;
+    if found > 0 {
+        return Err(anyhow!("{} violation(s) found", found));
+    }
+    Ok(())
+}
+
+fn compile(column: &str, pattern: Option<&str>) -> Result<Option<Regex>> {
+    pattern
+        .map(|p| Regex::new(p).with_context(|| format!("Invalid regex for column `{}`", column)))
+        .transpose()
+}
+
+/// Enum members compare against the raw csv text.
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+impl std::str::FromStr for ValueType {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "string" => Ok(ValueType::String),
+            "integer" | "int" => Ok(ValueType::Integer),
+            "number" | "float" => Ok(ValueType::Number),
+            "boolean" | "bool" => Ok(ValueType::Boolean),
+            "date" | "datetime" => Ok(ValueType::Date),
+            _ => Err(anyhow!("Unsupported column type `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ValueType::String => "string",
+            ValueType::Integer => "integer",
+            ValueType::Number => "number",
+            ValueType::Boolean => "boolean",
+            ValueType::Date => "date",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.column {
+            Some(column) => write!(
+                f,
+                "row {}, column {} (`{}`): {}",
+                self.row, column, self.name, self.message
+            ),
+            None => write!(f, "row {} (`{}`): {}", self.row, self.name, self.message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    fn validate(schema: &Schema, csv: &str) -> (u64, String) {
+        let mut out = Vec::new();
+        let found =
+            validate_csv(csv.as_bytes(), schema, &CsvReaderOpts::default(), &mut out).unwrap();
+        (found, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_validate_juventus() {
+        for path in [
+            "fixtures/players.schema.toml",
+            "fixtures/players.schema.json",
+        ] {
+            let schema = Schema::load(path).unwrap();
+            let mut out = Vec::new();
+            let reader = File::open("assets/juventus.csv").unwrap();
+            let found = validate_csv(reader, &schema, &CsvReaderOpts::default(), &mut out).unwrap();
+            assert_eq!(found, 0, "{}: {}", path, String::from_utf8_lossy(&out));
+
+            let csv = "Name,Position,DOB,Nationality,Kit Number\n\
+                       Danilo,Right-Back,\"Jul 15, 1991 (28)\",Brazil,13\n\
+                       ,Stopper,\"Jul 15, 1991 (28)\",Brazil,ten\n";
+            let (found, out) = validate(&schema, csv);
+            assert_eq!(found, 3, "{}", path);
+            assert_eq!(
+                out,
+                "row 3, column 1 (`Name`): required value is missing\n\
+                 row 3, column 2 (`Position`): `Stopper` is not one of Goalkeeper, Centre-Back, Left-Back, Right-Back, Defensive Midfield, Central Midfield, Left Winger, Right Winger, Second Striker, Centre-Forward\n\
+                 row 3, column 5 (`Kit Number`): expected integer, got `ten`\n"
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_rules() {
+        let schema = Schema::from_toml(
+            r#"
+            [columns.id]
+            required = true
+            [columns.code]
+            regex = "^[A-Z]{2}$"
+            [columns.when]
+            type = "date"
+            "#,
+        )
+        .unwrap();
+        let (found, out) = validate(&schema, "code,when\nIT,2019-08-12\nita,soon\n");
+        assert_eq!(found, 3);
+        assert_eq!(
+            out,
+            "row 1 (`id`): required column is missing\n\
+             row 3, column 1 (`code`): `ita` does not match /^[A-Z]{2}$/\n\
+             row 3, column 2 (`when`): expected date, got `soon`\n"
+        );
+        assert!(Schema::from_toml("[columns.id]\ntype = \"uuid\"").is_err());
+        assert!(Schema::from_toml("[columns.id]\nregex = \"(\"").is_err());
+
+        // a required name without a property is still checked for presence
+        let schema = Schema::from_json_schema(
+            r#"{"properties": {"code": {"type": "string"}}, "required": ["id", "code"]}"#,
+        )
+        .unwrap();
+        let (found, out) = validate(&schema, "code\nIT\n");
+        assert_eq!(found, 1);
+        assert_eq!(out, "row 1 (`id`): required column is missing\n");
+        assert_eq!(validate(&schema, "code,id\nIT,\n").0, 1);
+    }
+}
@@ -1,45 +1,397 @@
+use anyhow::anyhow;
 use rand::seq::SliceRandom;
-use zxcvbn::zxcvbn;
+use rand::Rng;
+use serde::Serialize;
+use std::collections::HashSet;
+use zxcvbn::time_estimates::CrackTimeSeconds;
+use zxcvbn::{zxcvbn, Entropy};
 
-const UPPER: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
-const LOWER: &[u8] = b"abcdefghijkmnopqrstuvwxyz";
-const NUMBER: &[u8] = b"123456789";
-const SYMBOL: &[u8] = b"@#$%&*!.?_";
+use crate::cli::genpass::CharsetOpts;
 
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+/// No `l`, too easily mistaken for `1` or `I`.
+const LOWER: &str = "abcdefghijkmnopqrstuvwxyz";
+/// No `0`, too easily mistaken for `O`.
+const NUMBER: &str = "123456789";
+const SYMBOL: &str = "@#$%&*!.?_";
+/// Dropped by `--no-ambiguous`, on top of the `l` and `0` that are never used.
+const AMBIGUOUS: &str = "IO1";
+/// Passwords tried for `min_score` before giving up.
+const MAX_ATTEMPTS: usize = 100;
+/// 7776 common english words, one per line, the size of a five dice diceware list.
+const WORDLIST: &str = include_str!("../../assets/wordlist.txt");
+
+/// How zxcvbn rates a password, laid out like the zxcvbn result object.
+#[derive(Debug, Serialize)]
+pub struct StrengthReport {
+    pub score: u8,
+    pub guesses_log10: f64,
+    pub crack_times_seconds: CrackTimes<f64>,
+    pub crack_times_display: CrackTimes<String>,
+    pub feedback: Feedback,
+    /// Bits of entropy from the word count and list size, for passphrases
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entropy: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrackTimes<T> {
+    pub online_throttling_100_per_hour: T,
+    pub online_no_throttling_10_per_second: T,
+    pub offline_slow_hashing_1e4_per_second: T,
+    pub offline_fast_hashing_1e10_per_second: T,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Feedback {
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+impl From<&Entropy> for StrengthReport {
+    fn from(estimate: &Entropy) -> Self {
+        let times = estimate.crack_times();
+        let times = [
+            times.online_throttling_100_per_hour(),
+            times.online_no_throttling_10_per_second(),
+            times.offline_slow_hashing_1e4_per_second(),
+            times.offline_fast_hashing_1e10_per_second(),
+        ];
+        let seconds = times.map(|t| match t {
+            CrackTimeSeconds::Integer(i) => i as f64,
+            CrackTimeSeconds::Float(f) => f,
+        });
+        let feedback = estimate.feedback().as_ref();
+        Self {
+            score: estimate.score(),
+            guesses_log10: estimate.guesses_log10(),
+            crack_times_seconds: seconds.into(),
+            crack_times_display: times.map(|t| t.to_string()).into(),
+            feedback: Feedback {
+                warning: feedback.and_then(|f| f.warning()).map(|w| w.to_string()),
+                suggestions: feedback
+                    .map(|f| f.suggestions().iter().map(|s| s.to_string()).collect())
+                    .unwrap_or_default(),
+            },
+            entropy: None,
+        }
+    }
+}
+
+impl<T> From<[T; 4]> for CrackTimes<T> {
+    fn from([a, b, c, d]: [T; 4]) -> Self {
+        Self {
+            online_throttling_100_per_hour: a,
+            online_no_throttling_10_per_second: b,
+            offline_slow_hashing_1e4_per_second: c,
+            offline_fast_hashing_1e10_per_second: d,
+        }
+    }
+}
+
+/// Generate a password of `len` characters with at least one of each selected class,
+/// retrying until zxcvbn scores it `min_score` or better.
 pub fn process_genpass(
     len: u8,
-    uppercase: bool,
-    lowercase: bool,
-    number: bool,
-    symbol: bool,
-) -> anyhow::Result<String> {
+    charset: &CharsetOpts,
+    min_score: u8,
+) -> anyhow::Result<(String, StrengthReport)> {
+    let (classes, chars) = char_classes(charset)?;
+    if chars.is_empty() {
+        return Err(anyhow!("Select at least one character class"));
+    }
+    if len == 0 {
+        return Err(anyhow!("Length must be at least 1"));
+    }
+    if usize::from(len) < classes.len() {
+        return Err(anyhow!(
+            "Length {} is too short for {} character classes",
+            len,
+            classes.len()
+        ));
+    }
     let mut rng = rand::thread_rng();
-    let mut password = Vec::new();
-    let mut chars = Vec::new();
+    retry(min_score, || {
+        // one character from each class, the rest from all of them
+        let mut password: Vec<char> = classes
+            .iter()
+            .map(|class| *class.choose(&mut rng).expect("class won't be empty"))
+            .collect();
+        for _ in password.len()..usize::from(len) {
+            password.push(*chars.choose(&mut rng).expect("chars won't be empty"));
+        }
+        password.shuffle(&mut rng);
+        password.into_iter().collect()
+    })
+}
 
-    if uppercase {
-        chars.extend_from_slice(UPPER);
-        password.push(*UPPER.choose(&mut rng).expect("UPPER won't be empty"));
+/// Call `generate` until a result scores `min_score`, for at most `MAX_ATTEMPTS` tries.
+fn retry(
+    min_score: u8,
+    mut generate: impl FnMut() -> String,
+) -> anyhow::Result<(String, StrengthReport)> {
+    let mut best = 0;
+    for _ in 0..MAX_ATTEMPTS {
+        let password = generate();
+        let estimate = zxcvbn(&password, &[])?;
+        if estimate.score() >= min_score {
+            return Ok((password, StrengthReport::from(&estimate)));
+        }
+        best = best.max(estimate.score());
     }
-    if lowercase {
-        chars.extend_from_slice(LOWER);
-        password.push(*LOWER.choose(&mut rng).expect("LOWER won't be empty"));
+    Err(anyhow!(
+        "No password reached score {} in {} attempts (best was {}), try a longer one",
+        min_score,
+        MAX_ATTEMPTS,
+        best
+    ))
+}
+
+/// The required classes after exclusions, and every character to draw from.
+fn char_classes(charset: &CharsetOpts) -> anyhow::Result<(Vec<Vec<char>>, Vec<char>)> {
+    let excluded =
+        |c: &char| charset.exclude.contains(*c) || (charset.no_ambiguous && AMBIGUOUS.contains(*c));
+    let symbols = charset.symbols.as_deref().unwrap_or(SYMBOL);
+    let selected = [
+        ("uppercase", charset.uppercase, UPPER),
+        ("lowercase", charset.lowercase, LOWER),
+        ("number", charset.number, NUMBER),
+        ("symbol", charset.symbol, symbols),
+    ];
+
+    let mut classes = Vec::new();
+    for (name, _, set) in selected.into_iter().filter(|(_, on, _)| *on) {
+        let mut seen = HashSet::new();
+        let class: Vec<char> = set
+            .chars()
+            .filter(|c| !excluded(c) && seen.insert(*c))
+            .collect();
+        if class.is_empty() {
+            return Err(anyhow!("No {} characters left after exclusions", name));
+        }
+        classes.push(class);
     }
-    if number {
-        chars.extend_from_slice(NUMBER);
-        password.push(*NUMBER.choose(&mut rng).expect("NUMBER won't be empty"));
+    let mut seen = HashSet::new();
+    let chars = classes
+        .iter()
+        .flat_map(|class| class.iter().copied())
+        .chain(charset.include.chars().filter(|c| !excluded(c)))
+        .filter(|c| seen.insert(*c))
+        .collect();
+    Ok((classes, chars))
+}
+
+/// Build a passphrase of `words` random words from the bundled list, or from
+/// `wordlist` (one word per line, diceware style `11111<tab>word` lines work too).
+pub fn process_genphrase(
+    words: u8,
+    separator: &str,
+    capitalize: bool,
+    digit: bool,
+    wordlist: Option<&str>,
+    min_score: u8,
+) -> anyhow::Result<(String, StrengthReport)> {
+    let contents = match wordlist {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => WORDLIST.to_string(),
+    };
+    let list = parse_wordlist(&contents);
+    if list.len() < 2 {
+        return Err(anyhow!("Word list needs at least two distinct words"));
     }
-    if symbol {
-        chars.extend_from_slice(SYMBOL);
-        password.push(*SYMBOL.choose(&mut rng).expect("SYMBOL won't be empty"));
+
+    let mut rng = rand::thread_rng();
+    let (passphrase, mut report) = retry(min_score, || {
+        let mut chosen: Vec<String> = (0..words)
+            .map(|_| {
+                let word = *list.choose(&mut rng).expect("list won't be empty");
+                if capitalize {
+                    let mut chars = word.chars();
+                    chars.next().map_or(String::new(), |c| {
+                        c.to_uppercase().collect::<String>() + chars.as_str()
+                    })
+                } else {
+                    word.to_string()
+                }
+            })
+            .collect();
+        if digit {
+            // a digit appended to one of the words
+            let i = rng.gen_range(0..chosen.len());
+            chosen[i].push(char::from(b'0' + rng.gen_range(0..10)));
+        }
+        chosen.join(separator)
+    })?;
+    let mut entropy = f64::from(words) * (list.len() as f64).log2();
+    if digit {
+        entropy += (10.0 * f64::from(words)).log2();
     }
+    report.entropy = Some(entropy);
+    Ok((passphrase, report))
+}
+
+/// Distinct words of a list, keeping the last column of each line.
+fn parse_wordlist(contents: &str) -> Vec<&str> {
+    let mut seen = HashSet::new();
+    contents
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .filter(|word| seen.insert(*word))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn charset(uppercase: bool, lowercase: bool, number: bool, symbol: bool) -> CharsetOpts {
+        CharsetOpts {
+            uppercase,
+            lowercase,
+            number,
+            symbol,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_genpass_classes() -> anyhow::Result<()> {
+        let password = process_genpass(12, &charset(false, true, true, false), 0)?.0;
+        assert_eq!(password.len(), 12);
+        assert!(password
+            .chars()
+            .all(|c| LOWER.contains(c) || NUMBER.contains(c)));
+        assert!(password.chars().any(|c| NUMBER.contains(c)));
+        assert!(process_genpass(12, &charset(false, false, false, false), 0).is_err());
+        assert!(process_genpass(3, &CharsetOpts::default(), 0).is_err());
+        let include_only = CharsetOpts {
+            include: "ab".into(),
+            ..charset(false, false, false, false)
+        };
+        assert_eq!(
+            process_genpass(0, &include_only, 0)
+                .unwrap_err()
+                .to_string(),
+            "Length must be at least 1"
+        );
+        // `l` and `0` stay out by default, as they always have
+        for _ in 0..20 {
+            let password = process_genpass(32, &CharsetOpts::default(), 0)?.0;
+            assert!(!password.contains(['l', '0']));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_genpass_custom_charset() -> anyhow::Result<()> {
+        let opts = CharsetOpts {
+            symbols: Some("-_".into()),
+            exclude: "xyz".into(),
+            no_ambiguous: true,
+            ..Default::default()
+        };
+        for _ in 0..20 {
+            let password = process_genpass(8, &opts, 0)?.0;
+            assert!(password.chars().any(|c| c == '-' || c == '_'));
+            assert!(password.chars().any(|c| c.is_ascii_uppercase()));
+            assert!(!password.contains(|c| "xyzlIO01&@".contains(c)));
+        }
+
+        let opts = CharsetOpts {
+            include: "€".into(),
+            ..charset(false, false, true, false)
+        };
+        let (classes, chars) = char_classes(&opts)?;
+        assert_eq!(classes.len(), 1);
+        assert_eq!(chars.len(), 10);
+
+        // a required class emptied by exclusions is an error, not silently dropped
+        let opts = CharsetOpts {
+            symbols: Some("&".into()),
+            exclude: "&".into(),
+            ..Default::default()
+        };
+        let err = process_genpass(16, &opts, 0).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No symbol characters left after exclusions"
+        );
+        let opts = CharsetOpts {
+            exclude: "23456789".into(),
+            no_ambiguous: true,
+            ..Default::default()
+        };
+        assert!(process_genpass(16, &opts, 0).is_err());
+
+        // only extra characters, no class to guarantee
+        let opts = CharsetOpts {
+            include: "ab".into(),
+            ..charset(false, false, false, false)
+        };
+        assert!(process_genpass(6, &opts, 0)?
+            .0
+            .chars()
+            .all(|c| c == 'a' || c == 'b'));
+        Ok(())
+    }
+
+    #[test]
+    fn test_genpass_min_score() -> anyhow::Result<()> {
+        let (_, report) = process_genpass(16, &CharsetOpts::default(), 4)?;
+        assert_eq!(report.score, 4);
+        assert!(report.guesses_log10 >= 10.0);
+
+        let err = process_genpass(4, &charset(false, true, false, false), 4).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("No password reached score 4 in 100 attempts"));
+
+        let report = StrengthReport::from(&zxcvbn("password1", &[])?);
+        let json = serde_json::to_value(&report)?;
+        assert_eq!(json["score"], 0);
+        assert_eq!(
+            json["crack_times_display"]["offline_fast_hashing_1e10_per_second"],
+            "less than a second"
+        );
+        assert!(json["feedback"]["warning"].is_string());
+        assert!(!report.feedback.suggestions.is_empty());
+        assert!(json.get("entropy").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_genphrase() -> anyhow::Result<()> {
+        let list = parse_wordlist(WORDLIST);
+        assert_eq!(list.len(), 7776);
+        // nothing that is easy to mistype, like `ahhh` next to `ahh` or `chose` next to `choose`
+        let mut squeezed = HashSet::new();
+        for word in &list {
+            let mut chars: Vec<char> = word.chars().collect();
+            assert!(
+                !chars.windows(3).any(|w| w[0] == w[1] && w[1] == w[2]),
+                "{}",
+                word
+            );
+            chars.dedup();
+            assert!(squeezed.insert(chars), "{}", word);
+        }
+        assert_eq!(
+            parse_wordlist("11111\tabacus\n11112\tabdomen\n\n11113 abacus\n"),
+            ["abacus", "abdomen"]
+        );
+
+        let phrase = process_genphrase(6, " ", false, false, None, 0)?.0;
+        let words: Vec<&str> = phrase.split(' ').collect();
+        assert_eq!(words.len(), 6);
+        assert!(words.iter().all(|w| WORDLIST.lines().any(|l| l == *w)));
 
-    for _ in 0..(len - password.len() as u8) {
-        password.push(*chars.choose(&mut rng).expect("chars won't be empty"));
+        let phrase = process_genphrase(4, ".", true, true, None, 0)?.0;
+        let words: Vec<&str> = phrase.split('.').collect();
+        assert_eq!(words.len(), 4);
+        assert!(words
+            .iter()
+            .all(|w| w.starts_with(|c: char| c.is_ascii_uppercase())));
+        assert_eq!(phrase.chars().filter(char::is_ascii_digit).count(), 1);
+        Ok(())
     }
-    password.shuffle(&mut rng);
-    let password = String::from_utf8(password)?;
-    let estimate = zxcvbn(&password, &[])?;
-    eprintln!("password strength: {}", estimate.score());
-    Ok(password)
 }
@@ -1,5 +1,18 @@
 pub mod b64;
+pub mod convert;
+pub mod csv_codegen;
 pub mod csv_convert;
+pub mod csv_filter;
+pub mod csv_join;
+pub mod csv_nested;
+pub mod csv_select;
+pub mod csv_sort;
+pub mod csv_split;
+pub mod csv_stats;
+pub mod csv_types;
+pub mod csv_validate;
 pub mod gen_pass;
 pub mod http_serve;
+pub mod pass_check;
+pub mod table;
 pub mod text;
@@ -0,0 +1,169 @@
+use anyhow::Result;
+use csv::StringRecord;
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use zxcvbn::zxcvbn;
+
+use crate::cli::genpass::{GenPassCheckOpts, ReportFormat};
+use crate::process::csv_convert::{csv_headers, csv_reader};
+use crate::process::csv_select::column_index;
+use crate::process::gen_pass::StrengthReport;
+use crate::utils::{read_data, write_data};
+
+/// The rating of one password, found on `line` of the input.
+#[derive(Debug, Serialize)]
+pub struct Checked {
+    pub line: u64,
+    /// Only kept with `--show`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(flatten)]
+    pub strength: StrengthReport,
+}
+
+impl fmt::Display for Checked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}", self.line)?;
+        if let Some(password) = &self.password {
+            write!(f, " ({})", password)?;
+        }
+        write!(f, ": score {}.", self.strength.score)?;
+        let feedback = &self.strength.feedback;
+        for message in feedback.warning.iter().chain(&feedback.suggestions) {
+            write!(f, " {}", message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Rate every password of the input: one per line, or the `--column` of a csv file.
+/// Blank entries are skipped since zxcvbn can't rate them.
+pub fn check_passwords(
+    reader: impl Read,
+    user_inputs: &[&str],
+    opts: &GenPassCheckOpts,
+) -> Result<Vec<Checked>> {
+    let mut checked = Vec::new();
+    let mut check = |line: u64, password: &str| -> Result<()> {
+        if password.is_empty() {
+            return Ok(());
+        }
+        let estimate = zxcvbn(password, user_inputs)?;
+        checked.push(Checked {
+            line,
+            password: opts.show.then(|| password.to_string()),
+            strength: StrengthReport::from(&estimate),
+        });
+        Ok(())
+    };
+
+    match &opts.column {
+        Some(column) => {
+            let mut reader = csv_reader(reader, &opts.reader)?;
+            let header = csv_headers(&mut reader, &opts.reader)?;
+            let index = column_index(&header, column)?;
+            let mut record = StringRecord::new();
+            while reader.read_record(&mut record)? {
+                let line = record.position().map_or(0, |p| p.line());
+                check(line, record.get(index).unwrap_or_default())?;
+            }
+        }
+        None => {
+            for (i, password) in BufReader::new(reader).lines().enumerate() {
+                check(i as u64 + 1, &password?)?;
+            }
+        }
+    }
+    Ok(checked)
+}
+
+pub fn process_genpass_check(opts: &GenPassCheckOpts) -> Result<()> {
+    let mut user_inputs = opts.user_inputs.clone();
+    if let Some(path) = &opts.user_dict {
+        let contents = fs::read_to_string(path)?;
+        user_inputs.extend(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|w| !w.is_empty())
+                .map(String::from),
+        );
+    }
+    let user_inputs: Vec<&str> = user_inputs.iter().map(String::as_str).collect();
+
+    let checked = check_passwords(read_data(&opts.input)?, &user_inputs, opts)?;
+    let mut writer = write_data(&opts.output)?;
+    match opts.report {
+        ReportFormat::Text => {
+            for entry in &checked {
+                writeln!(writer, "{}", entry)?;
+            }
+        }
+        ReportFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, &checked)?;
+            writeln!(writer)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::csv::CsvReaderOpts;
+
+    fn opts(column: Option<&str>, show: bool) -> GenPassCheckOpts {
+        GenPassCheckOpts {
+            input: "-".into(),
+            output: "-".into(),
+            column: column.map(String::from),
+            user_inputs: Vec::new(),
+            user_dict: None,
+            show,
+            report: ReportFormat::Text,
+            reader: CsvReaderOpts::default(),
+        }
+    }
+
+    #[test]
+    fn test_check_password_lines() -> Result<()> {
+        let input = "password1\n\nHq7$vLw2!zPe9#Rk\r\nacmecorp2024\n";
+        let checked = check_passwords(input.as_bytes(), &[], &opts(None, false))?;
+        assert_eq!(
+            checked.iter().map(|c| c.line).collect::<Vec<_>>(),
+            [1, 3, 4]
+        );
+        assert_eq!(checked[0].strength.score, 0);
+        assert!(checked[0].strength.feedback.warning.is_some());
+        assert_eq!(checked[1].strength.score, 4);
+
+        // the password never shows up unless asked for
+        let text = checked[0].to_string();
+        assert!(text.starts_with("line 1: score 0. "));
+        assert!(!text.contains("password1"));
+        assert!(serde_json::to_value(&checked[0])?.get("password").is_none());
+
+        let with_company = check_passwords(input.as_bytes(), &["AcmeCorp"], &opts(None, true))?;
+        assert!(with_company[2].strength.guesses_log10 < checked[2].strength.guesses_log10);
+        assert!(with_company[0]
+            .to_string()
+            .starts_with("line 1 (password1): score 0"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_password_column() -> Result<()> {
+        let input = "user,password\nann,hunter2\nbob,\ncid,\"Hq7$vLw2!zPe9#Rk\"\n";
+        let checked = check_passwords(input.as_bytes(), &[], &opts(Some("password"), false))?;
+        assert_eq!(checked.len(), 2);
+        assert_eq!((checked[0].line, checked[1].line), (2, 4));
+        assert!(checked[0].strength.score < checked[1].strength.score);
+
+        let err = check_passwords(input.as_bytes(), &[], &opts(Some("pass"), false)).unwrap_err();
+        assert!(err.to_string().starts_with("Unknown column `pass`"));
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use crate::process::csv_types::ColumnType;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// How a table is drawn: box-drawing borders for terminals, plain aligned text otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableStyle {
+    Boxed,
+    Plain,
+}
+
+/// Render rows as an aligned table. Cells wider than `max_width` display columns are
+/// truncated with `…`, numbers are right aligned.
+pub fn render_table(
+    header: &[String],
+    rows: &[Vec<String>],
+    style: TableStyle,
+    max_width: usize,
+) -> String {
+    let header: Vec<String> = header.iter().map(|c| clip(c, max_width)).collect();
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(|c| clip(c, max_width)).collect())
+        .collect();
+    let mut widths: Vec<usize> = header.iter().map(|c| c.width()).collect();
+    for row in &rows {
+        for (i, cell) in row.iter().enumerate() {
+            match widths.get_mut(i) {
+                Some(w) => *w = (*w).max(cell.width()),
+                None => widths.push(cell.width()),
+            }
+        }
+    }
+
+    let mut out = String::new();
+    match style {
+        TableStyle::Boxed => {
+            out.push_str(&border(&widths, '┌', '┬', '┐'));
+            out.push_str(&line(&header, &widths, "│ ", " │ ", " │"));
+            out.push_str(&border(&widths, '├', '┼', '┤'));
+            for row in &rows {
+                out.push_str(&line(row, &widths, "│ ", " │ ", " │"));
+            }
+            out.push_str(&border(&widths, '└', '┴', '┘'));
+        }
+        TableStyle::Plain => {
+            out.push_str(&line(&header, &widths, "", "  ", ""));
+            for row in &rows {
+                out.push_str(&line(row, &widths, "", "  ", ""));
+            }
+        }
+    }
+    out
+}
+
+fn border(widths: &[usize], left: char, mid: char, right: char) -> String {
+    let cells: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+    format!("{}{}{}\n", left, cells.join(&mid.to_string()), right)
+}
+
+fn line(cells: &[String], widths: &[usize], left: &str, mid: &str, right: &str) -> String {
+    let cells: Vec<String> = widths
+        .iter()
+        .enumerate()
+        .map(|(i, w)| {
+            let cell = cells.get(i).map(String::as_str).unwrap_or_default();
+            let pad = " ".repeat(w - cell.width());
+            match ColumnType::of(cell) {
+                ColumnType::Integer | ColumnType::Float => format!("{}{}", pad, cell),
+                _ => format!("{}{}", cell, pad),
+            }
+        })
+        .collect();
+    let line = format!("{}{}{}", left, cells.join(mid), right);
+    format!("{}\n", line.trim_end())
+}
+
+/// Flatten control characters and truncate to `max_width` display columns.
+fn clip(cell: &str, max_width: usize) -> String {
+    let cell: String = cell
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    if cell.width() <= max_width {
+        return cell;
+    }
+    let mut out = String::new();
+    let mut width = 0;
+    for c in cell.chars() {
+        let w = c.width().unwrap_or(0);
+        if width + w + 1 > max_width {
+            break;
+        }
+        out.push(c);
+        width += w;
+    }
+    out.push('…');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> (Vec<String>, Vec<Vec<String>>) {
+        let header = vec!["Name".to_string(), "Kit".to_string()];
+        let rows = vec![
+            vec!["Cristiano Ronaldo".to_string(), "7".to_string()],
+            vec!["武磊".to_string(), "10".to_string()],
+        ];
+        (header, rows)
+    }
+
+    #[test]
+    fn test_render_boxed_table() {
+        let (header, rows) = sample();
+        let table = render_table(&header, &rows, TableStyle::Boxed, 10);
+        assert_eq!(
+            table,
+            "┌────────────┬─────┐\n\
+             │ Name       │ Kit │\n\
+             ├────────────┼─────┤\n\
+             │ Cristiano… │   7 │\n\
+             │ 武磊       │  10 │\n\
+             └────────────┴─────┘\n"
+        );
+    }
+
+    #[test]
+    fn test_render_plain_table() {
+        let (header, rows) = sample();
+        let table = render_table(&header, &rows, TableStyle::Plain, 40);
+        assert_eq!(
+            table,
+            "Name               Kit\nCristiano Ronaldo    7\n武磊                10\n"
+        );
+    }
+}
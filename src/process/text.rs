@@ -1,3 +1,4 @@
+use crate::cli::genpass::CharsetOpts;
 use crate::cli::text::*;
 use crate::process_genpass;
 use crate::utils::read_data;
@@ -100,7 +101,7 @@ impl KeyLoader for Ed25519Verifier {
 
 impl KeyGenerator for Blake3 {
     fn generate_key() -> Result<Vec<Vec<u8>>> {
-        let key = process_genpass(32, true, true, true, true)?;
+        let key = process_genpass(32, &CharsetOpts::default(), 0)?.0;
         let key = key.trim().as_bytes().to_vec();
         Ok(vec![key])
     }
@@ -1,5 +1,5 @@
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufWriter, Read, Write};
 pub fn read_data(input: &str) -> anyhow::Result<Box<dyn Read>> {
     if input == "-" {
         Ok(Box::new(std::io::stdin()))
@@ -7,3 +7,11 @@ pub fn read_data(input: &str) -> anyhow::Result<Box<dyn Read>> {
         Ok(Box::new(File::open(input)?))
     }
 }
+
+pub fn write_data(output: &str) -> anyhow::Result<Box<dyn Write>> {
+    if output == "-" {
+        Ok(Box::new(BufWriter::new(std::io::stdout())))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(output)?)))
+    }
+}
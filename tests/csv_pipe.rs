@@ -0,0 +1,45 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[test]
+fn test_csv_reads_stdin_and_writes_stdout() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_wrcli"))
+        .args(["csv", "-f", "yaml"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"Name,Kit Number\nDanilo,13\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "- Name: Danilo\n  Kit Number: '13'\n"
+    );
+}
+
+#[test]
+fn test_csv_from_json_to_stdout() {
+    let output = Command::new(env!("CARGO_BIN_EXE_wrcli"))
+        .args([
+            "csv",
+            "--from",
+            "json",
+            "-i",
+            "fixtures/players.json",
+            "-o",
+            "-",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Name,Kit Number\nDanilo,13\nAaron Ramsey,8\n"
+    );
+}
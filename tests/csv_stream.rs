@@ -0,0 +1,95 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use wrcli::{convert_csv, CsvConvertOpts, CsvReaderOpts, CsvSelectOpts, OutputFormat};
+
+/// Tracks bytes currently allocated and the peak since the last reset.
+struct PeakAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let now = CURRENT.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(now, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static ALLOC: PeakAlloc = PeakAlloc;
+
+/// Generates `rows` csv lines lazily, so the input itself never sits in memory.
+struct CsvGen {
+    rows: usize,
+    line: usize,
+    pending: Vec<u8>,
+}
+
+impl CsvGen {
+    fn new(rows: usize) -> Self {
+        Self {
+            rows,
+            line: 0,
+            pending: b"id,name,position,kit\n".to_vec(),
+        }
+    }
+}
+
+impl Read for CsvGen {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() && self.line < self.rows {
+            self.pending = format!(
+                "{0},player {0},\"Centre-Back, left\",{1}\n",
+                self.line,
+                self.line % 99
+            )
+            .into_bytes();
+            self.line += 1;
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+fn peak_for(rows: usize, format: OutputFormat) -> usize {
+    let base = CURRENT.load(Ordering::SeqCst);
+    PEAK.store(base, Ordering::SeqCst);
+    let reader_opts = CsvReaderOpts::default();
+    let select_opts = CsvSelectOpts::default();
+    let opts = CsvConvertOpts::default();
+    convert_csv(
+        CsvGen::new(rows),
+        io::sink(),
+        format,
+        &reader_opts,
+        &select_opts,
+        &opts,
+    )
+    .unwrap();
+    PEAK.load(Ordering::SeqCst) - base
+}
+
+#[test]
+fn test_streaming_peak_memory_is_flat() {
+    for format in [OutputFormat::Json, OutputFormat::Ndjson, OutputFormat::Cbor] {
+        let small = peak_for(1_000, format);
+        let large = peak_for(100_000, format);
+        // 100x more rows must not need meaningfully more memory
+        assert!(
+            large < small * 2,
+            "{format}: peak {large} bytes for 100k rows vs {small} bytes for 1k rows"
+        );
+    }
+}